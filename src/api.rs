@@ -1,59 +1,371 @@
-use jsonrpsee_core::Error::ParseError;
-
 use hex::FromHex;
-use parity_scale_codec::Decode;
+use jsonrpsee_core::client::Subscription;
+use jsonrpsee_core::rpc_params;
+use log::{info, warn};
 use parity_scale_codec::Encode;
+use parity_scale_codec::{Decode, DecodeAll};
+use sp_core::crypto::{AccountId32, Ss58Codec};
+use sp_core::hashing::blake2_256;
 use sp_core::hexdisplay::HexDisplay;
-use sp_runtime::traits::Extrinsic;
+use sp_runtime::traits::{BlakeTwo256, Extrinsic, Header as HeaderT};
+use sp_runtime::transaction_validity::{InvalidTransaction, TransactionValidityError};
+use sp_runtime::{ApplyExtrinsicResult, DispatchError};
+use sp_trie::{read_trie_value, LayoutV1, StorageProof};
+use sp_version::RuntimeVersion;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
+use crate::db::{TransactionRecord, DB};
 use crate::error::Error;
 use crate::key_store::*;
 use crate::rpc::*;
+use crate::types::Error as RuntimeError;
 use crate::types::*;
+use crate::units::Denomination;
+use crate::vault::Vault;
 
 const SUPPLY_KEY: &[u8] = b"SUPPLY_KEY";
 const MIN_FEE_KEY: &[u8] = b"MIN_FEE_KEY";
 const BALANCES_PREFIX_KEY: &[u8] = b"BAL_";
+const WASM_MAGIC: &[u8] = b"\0asm";
+// interval between the checks of a transaction whose subscription was lost, and the number of
+// checks it can miss from both the pool and the blocks before it is considered dropped
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+const DROPPED_POLLS: u32 = 3;
+// times in a row a storage watch subscribes again after losing its subscription
+const MAX_RESUBSCRIPTIONS: u32 = 5;
+
+/// Point of the transaction lifecycle at which submitting commands return
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WaitFor {
+    Submitted,
+    InBlock,
+    Finalized,
+}
+
+impl WaitFor {
+    pub const NAMES: [&'static str; 3] = ["submitted", "in-block", "finalized"];
+}
+
+impl FromStr for WaitFor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "submitted" => Ok(WaitFor::Submitted),
+            "in-block" => Ok(WaitFor::InBlock),
+            "finalized" => Ok(WaitFor::Finalized),
+            _ => Err(Error::Config(format!("Unknown wait for value: {}", s))),
+        }
+    }
+}
+
+/// Outcome of a submitted extrinsic, with the last status seen
+pub struct TxResult {
+    pub hash: String,
+    pub status: TransactionStatus,
+}
+
+/// Balance of an account before and after an operation is applied
+pub struct ProjectedBalance {
+    pub account: AccountId32,
+    pub before: u128,
+    pub after: u128,
+}
+
+impl ProjectedBalance {
+    fn new(account: [u8; 32], before: u128, after: u128) -> ProjectedBalance {
+        ProjectedBalance {
+            account: AccountId32::new(account),
+            before,
+            after,
+        }
+    }
+}
+
+/// Result of the pre-flight checks of an operation
+pub struct Preflight {
+    pub signer: AccountId32,
+    pub min_fee: u32,
+    pub supply: u128,
+    pub supply_after: u128,
+    pub balances: Vec<ProjectedBalance>,
+    pub error: Option<RuntimeError>,
+}
+
+/// Storage value of the runtime that can be queried or watched
+pub enum StorageItem {
+    Balance(AccountId32),
+    Supply,
+    MinFee,
+}
+
+impl StorageItem {
+    fn key(&self) -> Vec<u8> {
+        match self {
+            StorageItem::Balance(account) => API::get_user_balance_key(account.clone().into()),
+            StorageItem::Supply => SUPPLY_KEY.to_vec(),
+            StorageItem::MinFee => MIN_FEE_KEY.to_vec(),
+        }
+    }
+
+    /// The hex encoded storage key, as reported in storage change notifications
+    pub fn storage_key(&self) -> String {
+        format!("0x{}", HexDisplay::from(&self.key()))
+    }
+
+    /// Decodes a hex encoded storage value, a missing value counts as zero
+    pub fn decode(&self, value: Option<&str>) -> Result<u128, Error> {
+        match value {
+            Some(value) => {
+                self.decode_value(Some(&<Vec<u8>>::from_hex(value.trim_start_matches("0x"))?))
+            }
+            None => self.decode_value(None),
+        }
+    }
+
+    fn decode_value(&self, value: Option<&[u8]>) -> Result<u128, Error> {
+        let mut encoded = match value {
+            Some(value) => value,
+            None => return Ok(0),
+        };
+        let value = match self {
+            StorageItem::MinFee => u32::decode(&mut encoded)? as u128,
+            _ => u128::decode(&mut encoded)?,
+        };
+        Ok(value)
+    }
+}
+
+/// Changes of the watched storage items in a block
+pub struct StorageUpdate {
+    pub block: String,
+    pub changes: Vec<StorageChange>,
+}
+
+/// New value of a watched item, along with its position among the watched ones and its previous
+/// value, which is none for the first one
+pub struct StorageChange {
+    pub index: usize,
+    pub old: Option<u128>,
+    pub new: u128,
+}
+
+/// Subscription to the values of storage items that subscribes again when it is lost
+pub struct StorageWatch<'a> {
+    api: &'a API,
+    targets: &'a [StorageItem],
+    keys: Vec<String>,
+    values: Vec<Option<u128>>,
+    subs: Subscription<StorageChangeSet>,
+    resubscriptions: u32,
+}
+
+impl<'a> StorageWatch<'a> {
+    /// Waits for the next change of the watched values. The first update holds the current values,
+    /// after subscribing again the values that changed while the connection was lost are reported,
+    /// values that didn't change are left out
+    pub async fn next(&mut self) -> Result<StorageUpdate, Error> {
+        loop {
+            let update = self.next_change_set().await?;
+            if !update.changes.is_empty() {
+                return Ok(update);
+            }
+        }
+    }
+
+    async fn next_change_set(&mut self) -> Result<StorageUpdate, Error> {
+        let change_set = loop {
+            match self.subs.next().await {
+                Some(Ok(change_set)) => break change_set,
+                lost if self.resubscriptions < MAX_RESUBSCRIPTIONS => {
+                    if let Some(Err(err)) = lost {
+                        warn!("Subscription error: {}", err);
+                    }
+                    warn!("Storage subscription lost, subscribing again ...");
+                    self.resubscriptions += 1;
+                    self.subs = self.api.subscribe_storage(&self.keys).await?;
+                }
+                _ => return Err(Error::Node("Storage subscription closed".to_owned())),
+            }
+        };
+        self.resubscriptions = 0;
+        let mut changes = Vec::new();
+        for (key, value) in &change_set.changes {
+            if let Some(index) = self
+                .keys
+                .iter()
+                .position(|watched| watched.eq_ignore_ascii_case(key))
+            {
+                let new = self.targets[index].decode(value.as_deref())?;
+                let old = self.values[index].replace(new);
+                if old != Some(new) {
+                    changes.push(StorageChange { index, old, new });
+                }
+            }
+        }
+        Ok(StorageUpdate {
+            block: change_set.block,
+            changes,
+        })
+    }
+}
+
+/// Outcome of an indexer run, the range of blocks processed and the number of wallet transactions found
+pub struct IndexSummary {
+    pub from: u64,
+    pub to: u64,
+    pub found: usize,
+}
+
+/// Settings of the client that don't come from the node, the cli fills them from the environment
+#[derive(Debug, Clone, Default)]
+pub struct ApiOptions {
+    /// Seed the admin account is derived from, its key is expected in the keystore
+    pub admin_seed: Option<String>,
+    /// Address of the admin account, used when there is no admin seed
+    pub admin_account: Option<String>,
+    /// Denomination of the token, read from the chain properties when not set
+    pub denomination: Option<Denomination>,
+    /// Check every storage read against a proof instead of trusting the node
+    pub verify: bool,
+}
 
 pub struct API {
     rpc: RPC,
-    pub keystore: KeyStore,
+    wallet: Option<Wallet>,
+    options: ApiOptions,
+}
+
+/// The keys the client signs with and the database it records transactions in
+struct Wallet {
+    keystore: KeyStore,
+    db: DB,
 }
 
 impl API {
-    pub fn new(rpc: RPC, keystore: KeyStore) -> API {
-        API { rpc, keystore }
+    /// A client that only reads from the node and submits extrinsics signed elsewhere, see
+    /// [`API::with_wallet`] to sign and record transactions
+    pub fn new(rpc: RPC, options: ApiOptions) -> API {
+        API {
+            rpc,
+            wallet: None,
+            options,
+        }
+    }
+
+    /// Adds the wallet whose keys sign the calls, the transactions are recorded in its database
+    pub fn with_wallet(mut self, keystore: KeyStore, db: DB) -> API {
+        self.wallet = Some(Wallet { keystore, db });
+        self
+    }
+
+    pub fn db(&self) -> Result<&DB, Error> {
+        Ok(&self.wallet()?.db)
     }
 
-    pub async fn get_balance(&self, account: &str) -> Result<u128, Error> {
-        let balance_key = Self::get_user_balance_key(KeyStore::get_pub_key(account)?.0);
-        let value = self.get_value(&balance_key).await?;
+    pub fn keystore(&self) -> Result<&KeyStore, Error> {
+        Ok(&self.wallet()?.keystore)
+    }
+
+    fn wallet(&self) -> Result<&Wallet, Error> {
+        self.wallet.as_ref().ok_or_else(Self::no_wallet)
+    }
+
+    fn no_wallet() -> Error {
+        Error::Database("The client has no wallet, see API::with_wallet".to_owned())
+    }
+
+    pub fn options(&self) -> &ApiOptions {
+        &self.options
+    }
+
+    /// Unlocks the keys of the wallet accounts, see [`KeyStore::unlock_wallet`]
+    pub fn unlock_wallet(&mut self, vault: Vault) -> Result<(), Error> {
+        let wallet = self.wallet.as_mut().ok_or_else(Self::no_wallet)?;
+        wallet.keystore.unlock_wallet(&wallet.db, vault)
+    }
+
+    /// Adds the key of the mnemonic to the keystore, see [`KeyStore::add`]
+    pub fn add_key(&mut self, mnemonic: &str, scheme: Scheme) -> Result<AccountId32, Error> {
+        match &mut self.wallet {
+            Some(wallet) => wallet.keystore.add(mnemonic, scheme),
+            None => Err(Self::no_wallet()),
+        }
+    }
+
+    /// Reads the balance of the account, at the given block hash or at the best block if none is given.
+    /// Accounts that never received tokens have no balance stored, which reads as zero
+    pub async fn get_balance(&self, account: &str, at: Option<&str>) -> Result<u128, Error> {
+        let balance_key = Self::get_user_balance_key(KeyStore::get_pub_key(account)?.into());
+        let value = self.find_value(&balance_key, at).await?.unwrap_or_default();
         Ok(value)
     }
 
-    pub async fn get_min_fee(&self) -> Result<u32, Error> {
-        let value = self.get_value(MIN_FEE_KEY).await?;
+    pub async fn get_min_fee(&self, at: Option<&str>) -> Result<u32, Error> {
+        let value = self.get_value(MIN_FEE_KEY, at).await?;
         Ok(value)
     }
 
-    pub async fn get_supply(&self) -> Result<u128, Error> {
-        let value = self.get_value(SUPPLY_KEY).await?;
+    pub async fn get_supply(&self, at: Option<&str>) -> Result<u128, Error> {
+        let value = self.get_value(SUPPLY_KEY, at).await?;
         Ok(value)
     }
 
-    pub async fn set_min_fee(&self, fee: u32, tx_fee: u32) -> Result<String, Error> {
+    /// Resolves a block given either by hash or by number to its hash
+    pub async fn resolve_block(&self, block: &str) -> Result<String, Error> {
+        if block.starts_with("0x") {
+            let hash = <[u8; 32]>::from_hex(&block[2..])
+                .map_err(|_| Error::Config(format!("Invalid block hash: {}", block)))?;
+            return Ok(format!("0x{}", HexDisplay::from(&hash)));
+        }
+        let number = block.parse::<u64>().map_err(|_| {
+            Error::Config(format!(
+                "{} is neither a block hash nor a block number",
+                block
+            ))
+        })?;
+        self.get_block_hash(number).await
+    }
+
+    async fn get_block_hash(&self, number: u64) -> Result<String, Error> {
+        self.rpc
+            .call::<Option<String>>("chain_getBlockHash", rpc_params![number])
+            .await?
+            .ok_or(Error::Node(format!("Block: {} not found", number)))
+    }
+
+    pub async fn set_min_fee(
+        &self,
+        fee: u32,
+        tx_fee: u32,
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
         let result = self
-            .sign_and_send(&Self::get_admin_seed()?, Call::SetMinFee(fee), tx_fee)
+            .sign_and_send(
+                &self.get_admin_key()?,
+                Call::SetMinFee(fee),
+                tx_fee,
+                wait_for,
+            )
             .await?;
         Ok(result)
     }
 
-    pub async fn mint(&self, account: &str, amount: u128) -> Result<String, Error> {
+    pub async fn mint(
+        &self,
+        account: &str,
+        amount: u128,
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
         let result = self
             .sign_and_send(
-                &Self::get_admin_seed()?,
-                Call::Mint(KeyStore::get_pub_key(account)?.0, amount),
+                &self.get_admin_key()?,
+                Self::mint_call(account, amount)?,
                 0,
+                wait_for,
             )
             .await?;
         Ok(result)
@@ -61,90 +373,914 @@ impl API {
 
     pub async fn transfer(
         &self,
-        from_mnemonic: &str,
+        from_key: &AccountId32,
         to_address: &str,
         amount: u128,
         tx_fee: u32,
-    ) -> Result<String, Error> {
-        let from_key = self.keystore.add(from_mnemonic)?;
-        let to_key = KeyStore::get_pub_key(to_address)?;
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
+        let result = self
+            .sign_and_send(
+                from_key,
+                Self::transfer_call(from_key, to_address, amount)?,
+                tx_fee,
+                wait_for,
+            )
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn upgrade_runtime(
+        &self,
+        code: Vec<u8>,
+        tx_fee: u32,
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
         let result = self
             .sign_and_send(
-                from_mnemonic,
-                Call::Transfer(from_key.0, to_key.0, amount),
+                &self.get_admin_key()?,
+                Call::Upgrade(code),
                 tx_fee,
+                wait_for,
             )
             .await?;
         Ok(result)
     }
 
+    pub async fn get_runtime_version(&self) -> Result<RuntimeVersion, Error> {
+        let version = self
+            .rpc
+            .call("state_getRuntimeVersion", rpc_params![])
+            .await?;
+        Ok(version)
+    }
+
+    /// The token denomination of the options, or read from the chain properties if there is none
+    pub async fn get_denomination(&self) -> Result<Denomination, Error> {
+        if let Some(denomination) = &self.options.denomination {
+            return Ok(denomination.clone());
+        }
+        let properties: serde_json::Map<String, serde_json::Value> =
+            self.rpc.call("system_properties", rpc_params![]).await?;
+        Denomination::from_properties(&properties)
+    }
+
+    /// Reads a runtime code blob, either plain or compressed, checking that it holds a wasm module.
+    /// The blob is returned as read, the node takes care of decompressing it
+    pub fn read_runtime_code(path: &Path) -> Result<Vec<u8>, Error> {
+        let code = fs::read(path)?;
+        let wasm = sp_maybe_compressed_blob::decompress(
+            &code,
+            sp_maybe_compressed_blob::CODE_BLOB_BOMB_LIMIT,
+        )?;
+        if !wasm.starts_with(WASM_MAGIC) {
+            return Err(Error::Decode(format!(
+                "{} is not a wasm module",
+                path.display()
+            )));
+        }
+        Ok(code)
+    }
+
+    pub fn mint_call(account: &str, amount: u128) -> Result<Call, Error> {
+        Ok(Call::Mint(KeyStore::get_pub_key(account)?.into(), amount))
+    }
+
+    pub fn transfer_call(
+        from_key: &AccountId32,
+        to_address: &str,
+        amount: u128,
+    ) -> Result<Call, Error> {
+        let to_key = KeyStore::get_pub_key(to_address)?;
+        Ok(Call::Transfer(
+            from_key.clone().into(),
+            to_key.into(),
+            amount,
+        ))
+    }
+
     pub async fn sign_and_send(
         &self,
-        seed: &str,
+        key: &AccountId32,
         call: Call,
         tx_fee: u32,
-    ) -> Result<String, Error> {
-        let key = self.keystore.add(seed)?;
-        let ext_payload = ExtrinsicPayload::new(call, tx_fee);
-        let signature = self.keystore.sign(&key, &ext_payload.encode())?;
-        let signature = Signature {
-            signature: signature.clone(),
-            origin: key.0.to_vec(),
-        };
-        let ext = BasicExtrinsic::new(ext_payload, Some(signature)).unwrap();
-        // println!("Encoded extrinsic: {:?}", HexDisplay::from(&ext.encode()));
-        let response = self.call_extrinsic(ext).await?;
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
+        let payload = ExtrinsicPayload::new(call, tx_fee);
+        if let Some(runtime_error) = self.preflight(key, &payload).await?.error {
+            return Err(Error::Runtime(runtime_error));
+        }
+        let ext = self.sign(key, payload)?;
+        let response = self.call_extrinsic(ext, wait_for).await?;
         Ok(response)
     }
 
-    async fn call_extrinsic(&self, ext: BasicExtrinsic) -> Result<String, Error> {
-        let param = HexDisplay::from(&ext.encode()).to_string();
-        let mut subs = self
+    /// The unsigned payload of the call, to be signed offline. Mints are signed by the admin without a
+    /// fee, other calls pay the given tx fee, or the current min fee
+    pub async fn build_payload(
+        &self,
+        call: Call,
+        tx_fee: Option<u32>,
+    ) -> Result<ExtrinsicPayload, Error> {
+        let tx_fee = match (&call, tx_fee) {
+            (Call::Mint(..), _) => 0,
+            (_, Some(tx_fee)) => tx_fee,
+            (_, None) => self.get_min_fee(None).await?,
+        };
+        Ok(ExtrinsicPayload::new(call, tx_fee))
+    }
+
+    /// Signs the payload with the key of the account, doesn't require a connection to the node
+    pub fn sign(
+        &self,
+        key: &AccountId32,
+        payload: ExtrinsicPayload,
+    ) -> Result<BasicExtrinsic, Error> {
+        let (origin, signature) = self.keystore()?.sign(key, &payload.encode())?;
+        let signature = Signature::new(signature, origin);
+        let ext = BasicExtrinsic::new(payload, Some(signature)).unwrap();
+        Ok(ext)
+    }
+
+    /// The account expected to sign the payload, the sender for transfers and the admin otherwise
+    pub fn get_expected_signer(&self, payload: &ExtrinsicPayload) -> Result<AccountId32, Error> {
+        match &payload.call {
+            Call::Transfer(from, _, _) => Ok(AccountId32::new(*from)),
+            _ => self.get_admin_key(),
+        }
+    }
+
+    /// Submits an extrinsic signed offline, checking its signature beforehand
+    pub async fn broadcast(
+        &self,
+        ext: BasicExtrinsic,
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
+        let signature = ext
+            .1
+            .as_ref()
+            .ok_or(Error::Keystore("Extrinsic is not signed".to_owned()))?;
+        if !signature.verify(&ext.0.encode()) {
+            return Err(Error::Keystore(
+                "Extrinsic signature is not valid for its payload".to_owned(),
+            ));
+        }
+        let response = self.call_extrinsic(ext, wait_for).await?;
+        Ok(response)
+    }
+
+    /// The hash the node and the ledger identify the extrinsic by
+    pub fn extrinsic_hash(ext: &BasicExtrinsic) -> String {
+        format!("0x{}", HexDisplay::from(&blake2_256(&ext.encode())))
+    }
+
+    /// Submits the extrinsic and follows its lifecycle until the wait for point is reached,
+    /// every status transition is reported as it happens. Over http it is only submitted
+    async fn call_extrinsic(
+        &self,
+        ext: BasicExtrinsic,
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
+        let encoded = ext.encode();
+        let hash = Self::extrinsic_hash(&ext);
+        let param = HexDisplay::from(&encoded).to_string();
+        let watch = self.rpc.supports_subscriptions();
+        if !watch && wait_for != WaitFor::Submitted {
+            return Err(Error::Node(
+                "Following a transaction needs a subscription, which http endpoints don't support, use --wait-for submitted or a ws or wss endpoint".to_owned(),
+            ));
+        }
+        // blocks from the best one at submission are searched if the connection is lost
+        let submitted_after = self.get_best_number().await?;
+        let subs = if watch {
+            self.rpc
+                .subscribe::<TransactionStatus>(
+                    "author_submitAndWatchExtrinsic",
+                    rpc_params![param],
+                    "author_unwatchExtrinsic",
+                )
+                .await
+                .map(Some)
+        } else {
+            self.rpc
+                .call_once::<String>("author_submitExtrinsic", rpc_params![param])
+                .await
+                .map(|_| None)
+        };
+        let subs = match subs {
+            Ok(subs) => subs,
+            // the node may have received it, sending it again could apply it twice
+            Err(err) if err.is_connection_error() => {
+                warn!(
+                    "Connection lost while submitting transaction: {}, {}, looking for it in the pool and in the blocks ...",
+                    hash, err
+                );
+                return match self.locate(&hash, submitted_after).await? {
+                    Some(status) if Self::reached(&status, wait_for) => {
+                        self.record_transaction(&hash, &ext, "submitted");
+                        self.report_status(&hash, &status);
+                        Ok(TxResult { hash, status })
+                    }
+                    Some(_) => {
+                        self.record_transaction(&hash, &ext, "submitted");
+                        self.resume_watch(hash, submitted_after, wait_for).await
+                    }
+                    None => {
+                        self.record_transaction(&hash, &ext, "unknown");
+                        Err(Error::Transaction(format!(
+                            "Connection lost while submitting transaction: {}, it is neither in the pool nor in a block yet, check its status before submitting it again",
+                            hash
+                        )))
+                    }
+                };
+            }
+            Err(err) => {
+                self.record_transaction(&hash, &ext, "rejected");
+                return Err(match self.diagnose(&ext, err.rpc_error_data()).await {
+                    Some(runtime_error) => Error::Runtime(runtime_error),
+                    None => err,
+                });
+            }
+        };
+        self.record_transaction(&hash, &ext, "submitted");
+        let mut subs = match subs {
+            Some(subs) => subs,
+            // without a subscription, the pool accepting the transaction is all there is to know
+            None => {
+                return Ok(TxResult {
+                    hash,
+                    status: TransactionStatus::Ready,
+                })
+            }
+        };
+        info!("Waiting for transaction result...");
+        while let Some(status) = subs.next().await {
+            let status = match status {
+                Ok(status) => status,
+                Err(err) => {
+                    warn!("Subscription error: {}", err);
+                    break;
+                }
+            };
+            self.report_status(&hash, &status);
+            let done = match &status {
+                TransactionStatus::Future
+                | TransactionStatus::Ready
+                | TransactionStatus::Broadcast(_) => wait_for == WaitFor::Submitted,
+                TransactionStatus::InBlock(_) => wait_for != WaitFor::Finalized,
+                // the transaction goes back to the pool, wait for it to be included again
+                TransactionStatus::Retracted(_) => false,
+                TransactionStatus::Finalized(_) => true,
+                TransactionStatus::FinalityTimeout(block) => {
+                    return Err(Error::Transaction(format!(
+                        "Transaction: {} was included in block: {} but it was not finalized in time",
+                        hash, block
+                    )))
+                }
+                TransactionStatus::Usurped(other) => {
+                    return Err(Error::Transaction(format!(
+                        "Transaction: {} was usurped by transaction: {}",
+                        hash, other
+                    )))
+                }
+                TransactionStatus::Dropped => {
+                    return Err(Error::Transaction(format!(
+                        "Transaction: {} was dropped from the pool",
+                        hash
+                    )))
+                }
+                TransactionStatus::Invalid => {
+                    return Err(match self.diagnose(&ext, None).await {
+                        Some(runtime_error) => Error::Runtime(runtime_error),
+                        None => Error::Transaction(format!(
+                            "Transaction: {} is invalid, make sure you are paying the right amount of fees, and have enough balance to pay fees and to perform the operation",
+                            hash
+                        )),
+                    })
+                }
+            };
+            if done {
+                return Ok(TxResult { hash, status });
+            }
+        }
+        warn!(
+            "Lost the subscription to transaction: {}, looking for it in the pool and in the blocks ...",
+            hash
+        );
+        self.resume_watch(hash, submitted_after, wait_for).await
+    }
+
+    /// Follows a transaction whose subscription was lost, typically because the node restarted, by
+    /// searching the blocks produced since it was submitted and checking that it is still in the pool
+    async fn resume_watch(
+        &self,
+        hash: String,
+        from: u64,
+        wait_for: WaitFor,
+    ) -> Result<TxResult, Error> {
+        let mut next = from;
+        let mut found: Option<(String, u64)> = None;
+        let mut missing = 0;
+        loop {
+            if found.is_none() {
+                let best = self.get_best_number().await?;
+                while found.is_none() && next <= best {
+                    let block = self.get_block_hash(next).await?;
+                    if self.block_contains(&block, &hash).await? {
+                        self.report_status(&hash, &TransactionStatus::InBlock(block.clone()));
+                        found = Some((block, next));
+                    } else {
+                        next += 1;
+                    }
+                }
+            }
+            match found.clone() {
+                Some((block, _)) if wait_for != WaitFor::Finalized => {
+                    return Ok(TxResult {
+                        hash,
+                        status: TransactionStatus::InBlock(block),
+                    })
+                }
+                Some((block, number)) => {
+                    if self.get_finalized_number().await? >= number {
+                        if self.get_block_hash(number).await? == block {
+                            let status = TransactionStatus::Finalized(block);
+                            self.report_status(&hash, &status);
+                            return Ok(TxResult { hash, status });
+                        }
+                        // the block didn't make it into the finalized chain, search again from there
+                        self.report_status(&hash, &TransactionStatus::Retracted(block));
+                        found = None;
+                    }
+                }
+                None => {
+                    missing = if self.in_pool(&hash).await? {
+                        if wait_for == WaitFor::Submitted {
+                            let status = TransactionStatus::Ready;
+                            self.report_status(&hash, &status);
+                            return Ok(TxResult { hash, status });
+                        }
+                        0
+                    } else {
+                        missing + 1
+                    };
+                    // a transaction leaves the pool before its block is seen, so it is only
+                    // given up on after missing from both for several polls
+                    if missing >= DROPPED_POLLS {
+                        self.report_status(&hash, &TransactionStatus::Dropped);
+                        return Err(Error::Transaction(format!(
+                            "Transaction: {} is neither in the pool nor in a block since it was submitted, it was dropped",
+                            hash
+                        )));
+                    }
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Whether a transaction located after the connection was lost needs no more following, being
+    /// in the pool is as far as submitted waits and being in a block as far as in-block waits
+    fn reached(status: &TransactionStatus, wait_for: WaitFor) -> bool {
+        match status {
+            TransactionStatus::InBlock(_) => wait_for != WaitFor::Finalized,
+            _ => wait_for == WaitFor::Submitted,
+        }
+    }
+
+    /// Where the transaction is: in one of the blocks from the given one on, or in the pool
+    pub async fn locate(&self, hash: &str, from: u64) -> Result<Option<TransactionStatus>, Error> {
+        let best = self.get_best_number().await?;
+        for number in from..=best {
+            let block = self.get_block_hash(number).await?;
+            if self.block_contains(&block, hash).await? {
+                return Ok(Some(TransactionStatus::InBlock(block)));
+            }
+        }
+        let in_pool = self.in_pool(hash).await?;
+        Ok(in_pool.then_some(TransactionStatus::Ready))
+    }
+
+    async fn in_pool(&self, hash: &str) -> Result<bool, Error> {
+        let pending: Vec<String> = self
             .rpc
-            .subscribe("author_submitAndWatchExtrinsic", &param)
+            .call("author_pendingExtrinsics", rpc_params![])
             .await?;
-        println!("Waiting for transaction result...");
-        while let Some(value) = subs.next().await {
-            // println!("Value: {:?}\n\n\n", value);
-            if let Err(outer_error) = value {
-                if let ParseError(err) = outer_error {
-                    if format!("{}", err).to_lowercase().contains("\"invalid\"") {
-                        return Err(Error::new("Extrinsic call failed, make sure you are paying the right amount of fees, and have enough balance to pay fees and to perform the operation"));
-                    }
-                } else {
-                    return Err(Error::new("Subscription error"));
+        Ok(pending
+            .iter()
+            .filter_map(|encoded| Self::decode_extrinsic(encoded))
+            .any(|ext| Self::extrinsic_hash(&ext) == hash))
+    }
+
+    fn report_status(&self, hash: &str, status: &TransactionStatus) {
+        info!("Transaction status: {}", status);
+        self.record_status(hash, status);
+    }
+
+    async fn block_contains(&self, block: &str, hash: &str) -> Result<bool, Error> {
+        let block: SignedBlock = self.rpc.call("chain_getBlock", rpc_params![block]).await?;
+        Ok(block
+            .block
+            .extrinsics
+            .iter()
+            .filter_map(|encoded| Self::decode_extrinsic(encoded))
+            .any(|ext| Self::extrinsic_hash(&ext) == hash))
+    }
+
+    /// Adds the extrinsic to the transactions ledger, a failure to record it doesn't fail the submission.
+    /// Without a wallet there is no ledger
+    fn record_transaction(&self, hash: &str, ext: &BasicExtrinsic, status: &str) {
+        let wallet = match &self.wallet {
+            Some(wallet) => wallet,
+            None => return,
+        };
+        let record = Self::ledger_record(hash, ext, status);
+        if let Err(err) = wallet.db.add_transaction(&record) {
+            warn!("Unable to record transaction: {}, {}", hash, err);
+        }
+    }
+
+    fn ledger_record(hash: &str, ext: &BasicExtrinsic, status: &str) -> TransactionRecord {
+        let signer = match (&ext.1, &ext.0.call) {
+            (Some(signature), _) => signature
+                .origin_account()
+                .map(|account| account.to_ss58check())
+                .unwrap_or_default(),
+            (None, Call::Transfer(from, _, _)) => AccountId32::new(*from).to_ss58check(),
+            (None, _) => String::new(),
+        };
+        let (call, counterparty, amount) = match &ext.0.call {
+            Call::Mint(account, amount) => ("mint", Some(*account), Some(*amount)),
+            Call::Transfer(_, to, amount) => ("transfer", Some(*to), Some(*amount)),
+            Call::SetMinFee(fee) => ("set-min-fee", None, Some(*fee as u128)),
+            Call::Upgrade(_) => ("upgrade", None, None),
+        };
+        TransactionRecord {
+            hash: hash.to_owned(),
+            signer,
+            call: call.to_owned(),
+            counterparty: counterparty.map(|account| AccountId32::new(account).to_ss58check()),
+            amount,
+            fee: ext.0.fee,
+            submitted_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            status: status.to_owned(),
+            block_hash: None,
+            block_number: None,
+        }
+    }
+
+    fn record_status(&self, hash: &str, status: &TransactionStatus) {
+        let wallet = match &self.wallet {
+            Some(wallet) => wallet,
+            None => return,
+        };
+        if let Err(err) =
+            wallet
+                .db
+                .update_transaction_status(hash, status.name(), status.block_hash())
+        {
+            warn!("Unable to record transaction: {}, {}", hash, err);
+        }
+    }
+
+    /// Works out which runtime error made the node reject the extrinsic, first from the validity error
+    /// returned by the node, then from a dry run, falling back to checks against the current state
+    async fn diagnose(
+        &self,
+        ext: &BasicExtrinsic,
+        rpc_error_data: Option<&str>,
+    ) -> Option<RuntimeError> {
+        if let Some(runtime_error) = rpc_error_data.and_then(Self::parse_validity_error) {
+            return Some(runtime_error);
+        }
+        if let Some(runtime_error) = self.dry_run(ext).await {
+            return Some(runtime_error);
+        }
+        self.precheck(ext).await.unwrap_or(None)
+    }
+
+    // The node reports custom validity errors as "Custom error: <code>"
+    fn parse_validity_error(data: &str) -> Option<RuntimeError> {
+        let code = data
+            .strip_prefix("Custom error: ")?
+            .trim()
+            .parse::<u8>()
+            .ok()?;
+        RuntimeError::from_custom_code(code)
+    }
+
+    /// Applies the extrinsic on top of the best block without submitting it, not every node exposes system_dryRun
+    async fn dry_run(&self, ext: &BasicExtrinsic) -> Option<RuntimeError> {
+        let param = HexDisplay::from(&ext.encode()).to_string();
+        let response = self.rpc.request("system_dryRun", &param).await.ok()??;
+        let encoded = <Vec<u8>>::from_hex(response.trim_start_matches("0x")).ok()?;
+        match ApplyExtrinsicResult::decode(&mut &encoded[..]).ok()? {
+            Err(TransactionValidityError::Invalid(InvalidTransaction::Custom(code))) => {
+                RuntimeError::from_custom_code(code)
+            }
+            Err(TransactionValidityError::Invalid(InvalidTransaction::BadProof)) => {
+                Some(RuntimeError::InvalidSignature)
+            }
+            Ok(Err(DispatchError::Module(module_error))) => {
+                RuntimeError::from_custom_code(module_error.error[0])
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks a signed extrinsic against the current chain state, returns the runtime error it would hit if any
+    pub async fn precheck(&self, ext: &BasicExtrinsic) -> Result<Option<RuntimeError>, Error> {
+        let signature = match &ext.1 {
+            Some(signature) => signature,
+            None => return Ok(Some(RuntimeError::NoSignature)),
+        };
+        let signer = match signature.origin_account() {
+            Some(signer) if signature.verify(&ext.0.encode()) => signer,
+            _ => return Ok(Some(RuntimeError::InvalidSignature)),
+        };
+        let preflight = self.preflight(&signer, &ext.0).await?;
+        Ok(preflight.error)
+    }
+
+    /// Checks the payload against the current chain state before it is signed: the admin key for admin calls,
+    /// the fee against the min fee, the signer balance against amount plus fee and the supply for mints.
+    /// Also projects the balances and supply after the operation
+    pub async fn preflight(
+        &self,
+        signer: &AccountId32,
+        payload: &ExtrinsicPayload,
+    ) -> Result<Preflight, Error> {
+        let supply: u128 = self.find_value(SUPPLY_KEY, None).await?.unwrap_or_default();
+        let min_fee: u32 = self
+            .find_value(MIN_FEE_KEY, None)
+            .await?
+            .unwrap_or_default();
+        let fee = payload.fee as u128;
+        let signer_account: [u8; 32] = signer.clone().into();
+        let mut preflight = Preflight {
+            signer: signer.clone(),
+            min_fee,
+            supply,
+            supply_after: supply,
+            balances: Vec::new(),
+            error: None,
+        };
+        let mut error = None;
+        let is_admin_call = !matches!(payload.call, Call::Transfer(..));
+        if is_admin_call {
+            if let Ok(admin) = self.get_admin_key() {
+                if admin != *signer {
+                    error = Some(RuntimeError::NoAdminKey);
                 }
-            } else if let Ok(map) = value {
-                if map.contains_key("inBlock") {
-                    return Ok(format!(
-                        "Tx included in block: {}",
-                        map.get("inBlock").unwrap()
+            }
+        }
+        match payload.call {
+            Call::Mint(account, amount) => {
+                let balance = self.find_balance(account).await?;
+                preflight.balances.push(ProjectedBalance::new(
+                    account,
+                    balance,
+                    balance.saturating_add(amount),
+                ));
+                preflight.supply_after = supply.saturating_add(amount);
+                if error.is_none() && supply.checked_add(amount).is_none() {
+                    error = Some(RuntimeError::SupplyOverflow);
+                }
+            }
+            Call::Transfer(from, to, amount) => {
+                let from_balance = self.find_balance(from).await?;
+                let mut from_after = from_balance.saturating_sub(amount).saturating_sub(fee);
+                if from != to {
+                    let to_balance = self.find_balance(to).await?;
+                    preflight.balances.push(ProjectedBalance::new(
+                        to,
+                        to_balance,
+                        to_balance.saturating_add(amount),
                     ));
+                } else {
+                    from_after = from_balance.saturating_sub(fee);
+                }
+                preflight
+                    .balances
+                    .insert(0, ProjectedBalance::new(from, from_balance, from_after));
+                if error.is_none() && payload.fee < min_fee {
+                    error = Some(RuntimeError::FeeTooLow);
+                }
+                if error.is_none() && from_balance < amount.saturating_add(fee) {
+                    error = Some(RuntimeError::BalanceTooLow);
                 }
             }
+            Call::SetMinFee(_) | Call::Upgrade(_) => {
+                let balance = self.find_balance(signer_account).await?;
+                preflight.balances.push(ProjectedBalance::new(
+                    signer_account,
+                    balance,
+                    balance.saturating_sub(fee),
+                ));
+                if error.is_none() && payload.fee < min_fee {
+                    error = Some(RuntimeError::FeeTooLow);
+                }
+                if error.is_none() && balance < fee {
+                    error = Some(RuntimeError::BalanceTooLow);
+                }
+            }
+        }
+        preflight.error = error;
+        Ok(preflight)
+    }
+
+    /// Runs the pre-flight checks and signs the extrinsic without submitting it
+    pub async fn simulate(
+        &self,
+        key: &AccountId32,
+        call: Call,
+        tx_fee: u32,
+    ) -> Result<(BasicExtrinsic, Preflight), Error> {
+        let payload = ExtrinsicPayload::new(call, tx_fee);
+        let preflight = self.preflight(key, &payload).await?;
+        let ext = self.sign(key, payload)?;
+        Ok((ext, preflight))
+    }
+
+    /// Walks the finalized blocks the indexer hasn't processed yet, recording every extrinsic signed by
+    /// or sent to a wallet account. Every account has its own cursor, stored along with each block, so
+    /// an interrupted run resumes where it stopped and accounts added since are scanned from genesis
+    pub async fn index(&self) -> Result<IndexSummary, Error> {
+        let db = self.db()?;
+        let cursors = db.get_index_cursors()?;
+        let to = self.get_finalized_number().await?;
+        let from = cursors.values().copied().min().unwrap_or(to + 1);
+        if from <= to {
+            info!("Indexing from block: {} ...", from);
+        }
+        let mut found = 0;
+        for number in from..=to {
+            let accounts: Vec<String> = cursors
+                .iter()
+                .filter(|(_, next_block)| **next_block <= number)
+                .map(|(account, _)| account.clone())
+                .collect();
+            let hash = self.get_block_hash(number).await?;
+            let block: SignedBlock = self.rpc.call("chain_getBlock", rpc_params![&hash]).await?;
+            let mut records = Vec::new();
+            for encoded in &block.block.extrinsics {
+                let ext = match Self::decode_extrinsic(encoded) {
+                    Some(ext) => ext,
+                    None => continue,
+                };
+                let ext_hash = Self::extrinsic_hash(&ext);
+                let mut record = Self::ledger_record(&ext_hash, &ext, "finalized");
+                let touches_wallet = accounts.contains(&record.signer)
+                    || record
+                        .counterparty
+                        .as_ref()
+                        .map_or(false, |account| accounts.contains(account));
+                if touches_wallet {
+                    record.block_hash = Some(hash.clone());
+                    record.block_number = Some(number);
+                    records.push(record);
+                }
+            }
+            db.add_indexed_transactions(&records, &accounts, number + 1)?;
+            if !records.is_empty() {
+                info!(
+                    "Block: {} has {} wallet transactions",
+                    number,
+                    records.len()
+                );
+            }
+            found += records.len();
+        }
+        Ok(IndexSummary { from, to, found })
+    }
+
+    /// Reads the values of all the items in a single query, so that they all come from the same block.
+    /// Returns the hash of that block along with the values
+    pub async fn query_storage(
+        &self,
+        items: &[StorageItem],
+        at: Option<&str>,
+    ) -> Result<(String, Vec<u128>), Error> {
+        if self.options.verify {
+            let keys: Vec<Vec<u8>> = items.iter().map(StorageItem::key).collect();
+            let (block, values) = self.read_verified(&keys, at).await?;
+            let values = items
+                .iter()
+                .zip(&values)
+                .map(|(item, value)| item.decode_value(value.as_deref()))
+                .collect::<Result<Vec<u128>, Error>>()?;
+            return Ok((block, values));
+        }
+        let keys: Vec<String> = items.iter().map(StorageItem::storage_key).collect();
+        let change_sets: Vec<StorageChangeSet> = self
+            .rpc
+            .call("state_queryStorageAt", rpc_params![&keys, at])
+            .await?;
+        let change_set = change_sets
+            .into_iter()
+            .next()
+            .ok_or(Error::Node("No storage returned by the node".to_owned()))?;
+        let mut values = Vec::new();
+        for (item, key) in items.iter().zip(&keys) {
+            let value = change_set
+                .changes
+                .iter()
+                .find(|(changed, _)| changed.eq_ignore_ascii_case(key))
+                .and_then(|(_, value)| value.as_deref());
+            values.push(item.decode(value)?);
         }
-        Err(Error::new("No response"))
+        Ok((change_set.block, values))
+    }
+
+    /// Watches the values of the targets, see [`StorageWatch::next`]
+    pub async fn watch_storage<'a>(
+        &'a self,
+        targets: &'a [StorageItem],
+    ) -> Result<StorageWatch<'a>, Error> {
+        let keys: Vec<String> = targets.iter().map(StorageItem::storage_key).collect();
+        let subs = self.subscribe_storage(&keys).await?;
+        Ok(StorageWatch {
+            api: self,
+            targets,
+            values: vec![None; keys.len()],
+            keys,
+            subs,
+            resubscriptions: 0,
+        })
+    }
+
+    async fn subscribe_storage(
+        &self,
+        keys: &[String],
+    ) -> Result<Subscription<StorageChangeSet>, Error> {
+        self.rpc
+            .subscribe(
+                "state_subscribeStorage",
+                rpc_params![keys],
+                "state_unsubscribeStorage",
+            )
+            .await
+    }
+
+    pub async fn get_best_number(&self) -> Result<u64, Error> {
+        let header: Header = self.rpc.call("chain_getHeader", rpc_params![]).await?;
+        header.number().ok_or(Error::Decode(format!(
+            "Invalid block number: {}",
+            header.number
+        )))
+    }
+
+    async fn get_finalized_number(&self) -> Result<u64, Error> {
+        let hash: String = self
+            .rpc
+            .call("chain_getFinalizedHead", rpc_params![])
+            .await?;
+        let header: Header = self.rpc.call("chain_getHeader", rpc_params![&hash]).await?;
+        header.number().ok_or(Error::Decode(format!(
+            "Invalid block number: {}",
+            header.number
+        )))
+    }
+
+    // Block bodies hold either the extrinsic encoding or, for opaque extrinsics, its length prefixed bytes
+    fn decode_extrinsic(encoded: &str) -> Option<BasicExtrinsic> {
+        let bytes = <Vec<u8>>::from_hex(encoded.trim_start_matches("0x")).ok()?;
+        if let Ok(ext) = BasicExtrinsic::decode_all(&mut &bytes[..]) {
+            return Some(ext);
+        }
+        let inner = <Vec<u8>>::decode_all(&mut &bytes[..]).ok()?;
+        BasicExtrinsic::decode_all(&mut &inner[..]).ok()
+    }
+
+    async fn find_balance(&self, account: [u8; 32]) -> Result<u128, Error> {
+        let balance = self
+            .find_value(&Self::get_user_balance_key(account), None)
+            .await?
+            .unwrap_or_default();
+        Ok(balance)
     }
 
-    async fn get_value<T: Decode>(&self, key: &[u8]) -> Result<T, Error> {
+    async fn get_value<T: Decode>(&self, key: &[u8], at: Option<&str>) -> Result<T, Error> {
+        self.find_value(key, at)
+            .await?
+            .ok_or(Error::Node("No value found".to_owned()))
+    }
+
+    async fn find_value<T: Decode>(
+        &self,
+        key: &[u8],
+        at: Option<&str>,
+    ) -> Result<Option<T>, Error> {
+        if self.options.verify {
+            let (_, mut values) = self.read_verified(&[key.to_vec()], at).await?;
+            let value = match values.remove(0) {
+                Some(encoded) => Some(T::decode(&mut &encoded[..])?),
+                None => None,
+            };
+            return Ok(value);
+        }
         let hex_key = HexDisplay::from(&key);
-        let response = self
+        let response: Option<String> = self
             .rpc
-            .request("state_getStorage", &hex_key.to_string())
+            .call("state_getStorage", rpc_params![hex_key.to_string(), at])
             .await?;
-        if let Some(mut encoded) = response {
-            encoded = encoded[2..].to_owned();
-            let decoded = <Vec<u8>>::from_hex(encoded).unwrap();
+        if let Some(encoded) = response {
+            let decoded = <Vec<u8>>::from_hex(encoded.strip_prefix("0x").ok_or_else(|| {
+                Error::Decode(format!("Storage value is not 0x prefixed: {}", encoded))
+            })?)?;
             let value = T::decode(&mut &decoded[..])?;
-            return Ok(value);
+            return Ok(Some(value));
         }
 
-        Err(Error::new("No value found"))
+        Ok(None)
+    }
+
+    /// Reads the values of the keys from a storage proof, checked against the state root of the header of
+    /// the block the proof was made at, and the header against the block hash.
+    /// Returns the block hash along with the values, None for keys proven to be absent.
+    /// Only a block hash from a trusted source makes the read trustless, without it the values are
+    /// those of whichever block the node picked, proven consistent with a header it provided
+    async fn read_verified(
+        &self,
+        keys: &[Vec<u8>],
+        at: Option<&str>,
+    ) -> Result<(String, Vec<Option<Vec<u8>>>), Error> {
+        let hex_keys: Vec<String> = keys
+            .iter()
+            .map(|key| format!("0x{}", HexDisplay::from(key)))
+            .collect();
+        let proof: ReadProof = self
+            .rpc
+            .call("state_getReadProof", rpc_params![&hex_keys, at])
+            .await?;
+        let header: VerifiableHeader = self
+            .rpc
+            .call("chain_getHeader", rpc_params![&proof.at])
+            .await?;
+        let values = Self::verify_read_proof(keys, at, &proof, &header)?;
+        Ok((proof.at, values))
+    }
+
+    /// Checks that the proof was made at the requested block and that the header is the one of that
+    /// block, then reads the values of the keys from the proof against the state root of the header
+    fn verify_read_proof(
+        keys: &[Vec<u8>],
+        at: Option<&str>,
+        proof: &ReadProof,
+        header: &VerifiableHeader,
+    ) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        // a genuine proof of another block would check out against the header of that block
+        if let Some(at) = at {
+            if !proof.at.eq_ignore_ascii_case(at) {
+                return Err(Error::Node(format!(
+                    "Storage proof requested at block: {} was made at block: {}",
+                    at, proof.at
+                )));
+            }
+        }
+        let header_hash = format!("0x{}", HexDisplay::from(&header.hash().0));
+        if !header_hash.eq_ignore_ascii_case(&proof.at) {
+            return Err(Error::Node(format!(
+                "Header returned for block: {} hashes to: {}",
+                proof.at, header_hash
+            )));
+        }
+        let mut nodes = Vec::new();
+        for node in &proof.proof {
+            nodes.push(<Vec<u8>>::from_hex(node.trim_start_matches("0x"))?);
+        }
+        let db = StorageProof::new(nodes).into_memory_db::<BlakeTwo256>();
+        let mut values = Vec::new();
+        for key in keys {
+            let value = read_trie_value::<LayoutV1<BlakeTwo256>, _>(
+                &db,
+                &header.state_root,
+                key,
+                None,
+                None,
+            )
+            .map_err(|err| {
+                Error::Node(format!(
+                    "Storage proof verification failed for block: {}, {:?}",
+                    proof.at, err
+                ))
+            })?;
+            values.push(value);
+        }
+        Ok(values)
     }
 
-    fn get_admin_seed() -> Result<String, Error> {
-        let admin_seed = std::env::var("ADMIN_SEED")?;
-        Ok(admin_seed)
+    /// The admin account, derived from the admin seed or given by address
+    pub fn get_admin_key(&self) -> Result<AccountId32, Error> {
+        if let Some(admin_seed) = &self.options.admin_seed {
+            return KeyStore::account_from_seed(admin_seed, Scheme::Sr25519);
+        }
+        match &self.options.admin_account {
+            Some(admin) => KeyStore::get_pub_key(admin),
+            None => Err(Error::Config(
+                "No admin account, set ADMIN_SEED or the admin of the network profile".to_owned(),
+            )),
+        }
     }
 
     fn get_user_balance_key(user: [u8; 32]) -> Vec<u8> {
@@ -152,7 +1288,88 @@ impl API {
     }
 }
 
-pub fn do_set_fee(fee: u32) -> Result<(), String> {
-    println!("Setting fee to: {}", fee);
-    Ok(())
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use sp_runtime::generic::Digest;
+    use sp_trie::{MemoryDB, TrieDBMutBuilder, TrieMut};
+
+    const KEY: &[u8] = b"MIN_FEE_KEY";
+
+    /// Header whose state root is the root of a trie holding the values, along with the proof of all its nodes
+    fn proven_state(values: &[(&[u8], &[u8])]) -> (VerifiableHeader, ReadProof) {
+        let mut db = MemoryDB::<BlakeTwo256>::default();
+        let mut root = Default::default();
+        {
+            let mut trie =
+                TrieDBMutBuilder::<LayoutV1<BlakeTwo256>>::new(&mut db, &mut root).build();
+            for (key, value) in values {
+                trie.insert(key, value).unwrap();
+            }
+        }
+        let header = VerifiableHeader::new(
+            1,
+            Default::default(),
+            root,
+            Default::default(),
+            Digest::default(),
+        );
+        let proof = ReadProof {
+            at: format!("0x{}", HexDisplay::from(&header.hash().0)),
+            proof: db
+                .drain()
+                .into_values()
+                .filter(|(_, references)| *references > 0)
+                .map(|(node, _)| format!("0x{}", HexDisplay::from(&node)))
+                .collect(),
+        };
+        (header, proof)
+    }
+
+    #[test]
+    fn reads_values_from_a_proof() {
+        let (header, proof) = proven_state(&[(KEY, &[10, 0, 0, 0])]);
+        let keys = [KEY.to_vec(), b"SUPPLY_KEY".to_vec()];
+        let values =
+            API::verify_read_proof(&keys, Some(&proof.at.to_uppercase()[..]), &proof, &header)
+                .unwrap();
+        assert_eq!(values, [Some(vec![10, 0, 0, 0]), None]);
+        assert!(API::verify_read_proof(&keys, None, &proof, &header).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_proof_of_another_block() {
+        let (header, proof) = proven_state(&[(KEY, &[10, 0, 0, 0])]);
+        let (other, _) = proven_state(&[(KEY, &[20, 0, 0, 0])]);
+        let requested = format!("0x{}", HexDisplay::from(&other.hash().0));
+        assert!(matches!(
+            API::verify_read_proof(&[KEY.to_vec()], Some(&requested), &proof, &header),
+            Err(Error::Node(_))
+        ));
+        // nor a header that isn't the one of the block
+        assert!(matches!(
+            API::verify_read_proof(&[KEY.to_vec()], None, &proof, &other),
+            Err(Error::Node(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_proof_that_does_not_match_the_state_root() {
+        let (header, _) = proven_state(&[(KEY, &[10, 0, 0, 0])]);
+        let (_, forged) = proven_state(&[(KEY, &[20, 0, 0, 0])]);
+        let forged = ReadProof {
+            at: format!("0x{}", HexDisplay::from(&header.hash().0)),
+            ..forged
+        };
+        assert!(matches!(
+            API::verify_read_proof(&[KEY.to_vec()], None, &forged, &header),
+            Err(Error::Node(_))
+        ));
+        let garbage = ReadProof {
+            proof: vec!["0xzz".to_owned()],
+            ..forged
+        };
+        assert!(API::verify_read_proof(&[KEY.to_vec()], None, &garbage, &header).is_err());
+    }
 }
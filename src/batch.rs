@@ -0,0 +1,511 @@
+use crate::api::{StorageItem, WaitFor, API};
+use crate::error::Error;
+use crate::key_store::KeyStore;
+use crate::types::{ExtrinsicPayload, TransactionStatus};
+use crate::units::Denomination;
+use csv::{ReaderBuilder, Trim, WriterBuilder};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+const RESULT_HEADERS: [&str; 9] = [
+    "row",
+    "recipient",
+    "amount",
+    "fee",
+    "hash",
+    "block",
+    "status",
+    "error",
+    "submitted_at",
+];
+// ledger statuses of a transaction that made it into a block, and of one that never will
+const INCLUDED: [&str; 3] = ["in-block", "finalized", "finality-timeout"];
+const RETRYABLE: [&str; 4] = ["rejected", "invalid", "dropped", "usurped"];
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SUCCESS: &str = "success";
+pub const STATUS_FAILED: &str = "failed";
+
+/// Transfer read from a batch file, rows are numbered from 1 after the header
+pub struct BatchRow {
+    pub row: usize,
+    pub recipient: String,
+    pub amount: u128,
+    pub fee: Option<u32>,
+}
+
+/// Outcome of a row as recorded in the results file
+pub struct BatchResult {
+    pub row: usize,
+    pub recipient: String,
+    pub amount: u128,
+    pub fee: u32,
+    pub hash: String,
+    pub block: String,
+    pub status: String,
+    pub error: String,
+    /// Best block number when the transfer was submitted, its block can't be older
+    pub submitted_at: Option<u64>,
+}
+
+impl BatchResult {
+    fn new(row: &BatchRow, fee: u32, hash: &str, submitted_at: Option<u64>) -> BatchResult {
+        BatchResult {
+            row: row.row,
+            recipient: row.recipient.clone(),
+            amount: row.amount,
+            fee,
+            hash: hash.to_owned(),
+            block: String::new(),
+            status: String::new(),
+            error: String::new(),
+            submitted_at,
+        }
+    }
+}
+
+/// Outcome of a batch run, the number of transfers submitted and of those that failed, along with
+/// the rows of previous runs left out as their transfer is still pending
+pub struct BatchSummary {
+    pub transfers: usize,
+    pub failed: usize,
+    pub pending: usize,
+}
+
+impl BatchSummary {
+    /// The batch is done once none of its transfers failed or is pending, otherwise it has to be run
+    /// again, which retries the failed transfers and settles the pending ones
+    pub fn check(&self, results_path: &Path) -> Result<(), Error> {
+        if self.failed > 0 {
+            return Err(Error::Transaction(format!(
+                "{} of {} transfers failed, run the batch again to retry them, results written to: {}",
+                self.failed,
+                self.transfers,
+                results_path.display()
+            )));
+        }
+        if self.pending > 0 {
+            return Err(Error::Transaction(format!(
+                "{} transfers done, {} transfers of a previous run are still pending, run the batch again once they are settled, results written to: {}",
+                self.transfers,
+                self.pending,
+                results_path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The results file of a batch when none is given, next to the batch file
+pub fn default_results_path(file: &Path) -> PathBuf {
+    file.with_extension("results.csv")
+}
+
+/// Where the transfer of a previous run that didn't succeed stands
+enum PreviousOutcome {
+    Included(String),
+    Pending(String),
+    Gone,
+}
+
+/// Validates every row of the batch and the total against the sender balance, then submits the
+/// transfers one by one, recording their outcome in the results file. Rows that succeeded in a
+/// previous run are skipped, the others are only retried once their transfer is known to never
+/// make it into a block, those still in the pool are left out and counted as pending
+pub async fn transfer(
+    api: &API,
+    sender: &str,
+    file: &Path,
+    results_path: &Path,
+    default_fee: u32,
+    wait_for: WaitFor,
+    units: &Denomination,
+) -> Result<BatchSummary, Error> {
+    let rows = read_rows(file, units)?;
+    let previous = read_results(results_path)?;
+    let mut results = ResultsWriter::open(results_path)?;
+    let min_fee = api.get_min_fee(None).await.unwrap_or_default();
+    let from_key = KeyStore::get_pub_key(sender)?;
+    let db = api.db()?;
+
+    let mut transfers = Vec::new();
+    let mut pending = 0;
+    let mut total: u128 = 0;
+    for row in &rows {
+        let recipient = db
+            .resolve_account(&row.recipient)
+            .map_err(|err| match err {
+                Error::Address { message, source } => Error::Address {
+                    message: format!("Row: {}, {}", row.row, message),
+                    source,
+                },
+                err => err,
+            })?;
+        KeyStore::get_pub_key(&recipient)?;
+        let fee = row.fee.unwrap_or(default_fee);
+        if fee < min_fee {
+            return Err(Error::Config(format!(
+                "Row: {} fee: {} is lower than the min fee: {}",
+                row.row,
+                units.describe(fee as u128),
+                units.describe(min_fee as u128)
+            )));
+        }
+        if let Some(previous) = previous.get(&row.row) {
+            if previous.recipient != row.recipient || previous.amount != row.amount {
+                return Err(Error::Config(format!(
+                    "Row: {} doesn't match the results file: {}, use a different results file for a different batch",
+                    row.row,
+                    results_path.display()
+                )));
+            }
+            if previous.status == STATUS_SUCCESS {
+                continue;
+            }
+            match previous_outcome(api, previous).await? {
+                PreviousOutcome::Included(block) => {
+                    results.write(&BatchResult {
+                        block,
+                        status: STATUS_SUCCESS.to_owned(),
+                        ..BatchResult::new(row, previous.fee, &previous.hash, previous.submitted_at)
+                    })?;
+                    continue;
+                }
+                PreviousOutcome::Pending(status) => {
+                    warn!(
+                        "Skipping row: {}, tx: {} is still {}, run the batch again once it is settled",
+                        row.row, previous.hash, status
+                    );
+                    pending += 1;
+                    continue;
+                }
+                PreviousOutcome::Gone => {}
+            }
+        }
+        total = total
+            .checked_add(row.amount)
+            .and_then(|total| total.checked_add(fee as u128))
+            .ok_or(Error::Config("Batch total overflows".to_owned()))?;
+        transfers.push((row, recipient, fee));
+    }
+
+    let (_, balances) = api
+        .query_storage(&[StorageItem::Balance(from_key.clone())], None)
+        .await?;
+    if total > balances[0] {
+        return Err(Error::Transaction(format!(
+            "Batch needs: {} including fees, but the balance of: {} is: {}",
+            units.format(total),
+            sender,
+            units.format(balances[0])
+        )));
+    }
+
+    info!(
+        "Transfering to {} recipients from: {}, {} rows already done ...",
+        transfers.len(),
+        sender,
+        rows.len() - transfers.len() - pending
+    );
+    let mut failed = 0;
+    for (row, recipient, fee) in &transfers {
+        let call = API::transfer_call(&from_key, recipient, row.amount)?;
+        let ext = api.sign(&from_key, ExtrinsicPayload::new(call, *fee))?;
+        let hash = API::extrinsic_hash(&ext);
+        let submitted_at = Some(api.get_best_number().await?);
+        results.write(&BatchResult {
+            status: STATUS_PENDING.to_owned(),
+            ..BatchResult::new(row, *fee, &hash, submitted_at)
+        })?;
+        info!(
+            "Row: {} transfering {} to {} ...",
+            row.row,
+            units.format(row.amount),
+            recipient
+        );
+        let result = match api.broadcast(ext, wait_for).await {
+            Ok(result) => BatchResult {
+                block: result.status.block_hash().unwrap_or_default().to_owned(),
+                status: STATUS_SUCCESS.to_owned(),
+                ..BatchResult::new(row, *fee, &hash, submitted_at)
+            },
+            Err(err) => {
+                warn!("Row: {} failed: {}", row.row, err);
+                failed += 1;
+                BatchResult {
+                    status: STATUS_FAILED.to_owned(),
+                    error: err.to_string(),
+                    ..BatchResult::new(row, *fee, &hash, submitted_at)
+                }
+            }
+        };
+        results.write(&result)?;
+    }
+    Ok(BatchSummary {
+        transfers: transfers.len(),
+        failed,
+        pending,
+    })
+}
+
+/// Looks the transfer up in the ledger, then in the pool and in the blocks since it was submitted,
+/// as the ledger doesn't know about transfers whose submission was cut short. A transfer found in
+/// neither was dropped, or never reached the node
+async fn previous_outcome(api: &API, previous: &BatchResult) -> Result<PreviousOutcome, Error> {
+    match api.db()?.find_transaction_status(&previous.hash)? {
+        Some((status, block)) if INCLUDED.contains(&status.as_str()) => {
+            return Ok(PreviousOutcome::Included(block.unwrap_or_default()))
+        }
+        Some((status, _)) if RETRYABLE.contains(&status.as_str()) => {
+            return Ok(PreviousOutcome::Gone)
+        }
+        _ => {}
+    }
+    // results written before the submission block was recorded are searched from genesis
+    let from = previous.submitted_at.unwrap_or_default();
+    Ok(match api.locate(&previous.hash, from).await? {
+        Some(TransactionStatus::InBlock(block)) => PreviousOutcome::Included(block),
+        Some(status) => PreviousOutcome::Pending(status.name().to_owned()),
+        None => PreviousOutcome::Gone,
+    })
+}
+
+/// Reads the rows of a batch file with a recipient,amount[,fee] header, amounts are in the given denomination
+pub fn read_rows(path: &Path, units: &Denomination) -> Result<Vec<BatchRow>, Error> {
+    let mut reader = ReaderBuilder::new()
+        .trim(Trim::All)
+        .flexible(true)
+        .from_path(path)?;
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = index + 1;
+        let recipient = record
+            .get(0)
+            .filter(|recipient| !recipient.is_empty())
+            .ok_or(Error::Config(format!("Row: {} has no recipient", row)))?;
+        let amount = record
+            .get(1)
+            .filter(|amount| !amount.is_empty())
+            .ok_or(Error::Config(format!("Row: {} has no amount", row)))?;
+        let amount = units
+            .parse(amount)
+            .map_err(|err| Error::Config(format!("Row: {}, {}", row, err)))?;
+        let fee = match record.get(2).filter(|fee| !fee.is_empty()) {
+            Some(fee) => Some(
+                fee.parse::<u32>()
+                    .map_err(|_| Error::Config(format!("Row: {} has an invalid fee", row)))?,
+            ),
+            None => None,
+        };
+        rows.push(BatchRow {
+            row,
+            recipient: recipient.to_owned(),
+            amount,
+            fee,
+        });
+    }
+    Ok(rows)
+}
+
+/// Reads the results recorded by previous runs, the last entry of every row wins
+pub fn read_results(path: &Path) -> Result<HashMap<usize, BatchResult>, Error> {
+    let mut results = HashMap::new();
+    if !path.exists() {
+        return Ok(results);
+    }
+    let mut reader = ReaderBuilder::new().flexible(true).from_path(path)?;
+    for record in reader.records() {
+        let record = record?;
+        let field = |index: usize| record.get(index).unwrap_or_default().to_owned();
+        let result = BatchResult {
+            row: field(0)
+                .parse()
+                .map_err(|_| Error::Decode("Invalid row number in the results file".to_owned()))?,
+            recipient: field(1),
+            amount: field(2)
+                .parse()
+                .map_err(|_| Error::Decode("Invalid amount in the results file".to_owned()))?,
+            fee: field(3)
+                .parse()
+                .map_err(|_| Error::Decode("Invalid fee in the results file".to_owned()))?,
+            hash: field(4),
+            block: field(5),
+            status: field(6),
+            error: field(7),
+            submitted_at: match field(8).as_str() {
+                "" => None,
+                number => Some(number.parse().map_err(|_| {
+                    Error::Decode("Invalid block number in the results file".to_owned())
+                })?),
+            },
+        };
+        results.insert(result.row, result);
+    }
+    Ok(results)
+}
+
+/// Results file, every result is appended and flushed as soon as it is known so that a crashed run
+/// can be resumed
+pub struct ResultsWriter {
+    writer: csv::Writer<File>,
+}
+
+impl ResultsWriter {
+    pub fn open(path: &Path) -> Result<ResultsWriter, Error> {
+        let is_new = !path.exists() || path.metadata()?.len() == 0;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = WriterBuilder::new().has_headers(false).from_writer(file);
+        if is_new {
+            writer.write_record(RESULT_HEADERS)?;
+            writer.flush()?;
+        }
+        Ok(ResultsWriter { writer })
+    }
+
+    pub fn write(&mut self, result: &BatchResult) -> Result<(), Error> {
+        self.writer.write_record([
+            result.row.to_string(),
+            result.recipient.clone(),
+            result.amount.to_string(),
+            result.fee.to_string(),
+            result.hash.clone(),
+            result.block.clone(),
+            result.status.clone(),
+            result.error.clone(),
+            result
+                .submitted_at
+                .map(|number| number.to_string())
+                .unwrap_or_default(),
+        ])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn result(row: usize, status: &str, error: &str) -> BatchResult {
+        BatchResult {
+            status: status.to_owned(),
+            error: error.to_owned(),
+            ..BatchResult::new(
+                &BatchRow {
+                    row,
+                    recipient: format!("recipient-{}", row),
+                    amount: 1_500_000,
+                    fee: None,
+                },
+                10,
+                &format!("0x0{}", row),
+                Some(row as u64),
+            )
+        }
+    }
+
+    #[test]
+    fn a_batch_with_failed_or_pending_transfers_is_not_done() {
+        let results_path = default_results_path(Path::new("batch.csv"));
+        assert_eq!(results_path, Path::new("batch.results.csv"));
+        let summary = |failed, pending| BatchSummary {
+            transfers: 3,
+            failed,
+            pending,
+        };
+        assert!(summary(0, 0).check(&results_path).is_ok());
+        assert!(matches!(
+            summary(1, 0).check(&results_path),
+            Err(Error::Transaction(_))
+        ));
+        assert!(matches!(
+            summary(0, 1).check(&results_path),
+            Err(Error::Transaction(_))
+        ));
+    }
+
+    #[test]
+    fn reads_rows_in_the_denomination() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch.csv");
+        fs::write(&path, "recipient,amount,fee\n alice , 1.5 ,\nbob,2,12\n").unwrap();
+        let rows = read_rows(&path, &Denomination::new(6, "UNIT").unwrap()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            (
+                rows[0].row,
+                rows[0].recipient.as_str(),
+                rows[0].amount,
+                rows[0].fee
+            ),
+            (1, "alice", 1_500_000, None)
+        );
+        assert_eq!((rows[1].amount, rows[1].fee), (2_000_000, Some(12)));
+
+        for invalid in ["alice,\n", ",1\n", "alice,1,high\n", "alice,1.2.3\n"] {
+            fs::write(&path, format!("recipient,amount,fee\n{}", invalid)).unwrap();
+            assert!(
+                matches!(
+                    read_rows(&path, &Denomination::raw()),
+                    Err(Error::Config(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn resumes_from_the_last_result_of_every_row() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("batch.results.csv");
+        assert!(read_results(&path).unwrap().is_empty());
+
+        let mut writer = ResultsWriter::open(&path).unwrap();
+        writer.write(&result(1, STATUS_PENDING, "")).unwrap();
+        writer.write(&result(2, STATUS_PENDING, "")).unwrap();
+        writer.write(&result(1, STATUS_SUCCESS, "")).unwrap();
+        drop(writer);
+        // a later run appends to the file, without writing the headers again
+        let mut writer = ResultsWriter::open(&path).unwrap();
+        writer
+            .write(&result(2, STATUS_FAILED, "Balance too low, with a comma"))
+            .unwrap();
+        drop(writer);
+        assert_eq!(
+            fs::read_to_string(&path)
+                .unwrap()
+                .matches("row,recipient")
+                .count(),
+            1
+        );
+
+        let results = read_results(&path).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[&1].status, STATUS_SUCCESS);
+        assert_eq!(results[&1].hash, "0x01");
+        assert_eq!((results[&1].amount, results[&1].fee), (1_500_000, 10));
+        assert_eq!(results[&2].status, STATUS_FAILED);
+        assert_eq!(results[&2].error, "Balance too low, with a comma");
+        assert_eq!(results[&2].submitted_at, Some(2));
+
+        // files written before the submission block was recorded have no such column
+        fs::write(
+            &path,
+            "row,recipient,amount,fee,hash,block,status,error\n1,alice,5,10,0x01,,pending,\n",
+        )
+        .unwrap();
+        let results = read_results(&path).unwrap();
+        assert_eq!(results[&1].submitted_at, None);
+        assert_eq!(results[&1].status, STATUS_PENDING);
+
+        fs::write(&path, "row,recipient\nfirst,alice\n").unwrap();
+        assert!(matches!(read_results(&path), Err(Error::Decode(_))));
+    }
+}
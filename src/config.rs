@@ -0,0 +1,304 @@
+use crate::api::ApiOptions;
+use crate::error::Error;
+use crate::units::{Denomination, DEFAULT_SYMBOL};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "wallet-cli";
+
+/// Settings of a named network, a value that isn't set is read from the other sources of settings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkProfile {
+    pub endpoint: String,
+    /// Endpoints of the same network to fail over to, in order, when the endpoint can't be reached
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<String>,
+    pub database: String,
+    pub token_decimals: Option<u8>,
+    pub token_symbol: Option<String>,
+    /// Address of the admin account, its key has to be in the wallet keystore
+    pub admin: Option<String>,
+}
+
+impl NetworkProfile {
+    pub fn endpoints(&self) -> Vec<String> {
+        let mut endpoints = vec![self.endpoint.clone()];
+        endpoints.extend(self.fallbacks.iter().cloned());
+        endpoints
+    }
+
+    /// The profile as the variables the wallet is configured with
+    pub fn vars(&self) -> Vars {
+        let values = [
+            ("ADDR", Some(self.endpoints().join(","))),
+            ("DATABASE_FILE", Some(self.database.clone())),
+            ("TOKEN_DECIMALS", self.token_decimals.map(|d| d.to_string())),
+            ("TOKEN_SYMBOL", self.token_symbol.clone()),
+            ("ADMIN_ACCOUNT", self.admin.clone()),
+        ];
+        values
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_owned(), value?)))
+            .collect()
+    }
+}
+
+/// Splits a comma separated list of endpoints
+pub fn split_endpoints(endpoints: &str) -> Vec<String> {
+    endpoints
+        .split(',')
+        .map(str::trim)
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Values of settings by variable name, like the ones of the environment or of a .env file
+pub type Vars = HashMap<String, String>;
+
+/// Settings of the wallet, every one is read from the first source that sets it. The sources are
+/// given in order of precedence, for the cli: flags, the environment, the network profile and .env
+pub struct Settings {
+    sources: Vec<Vars>,
+}
+
+impl Settings {
+    pub fn new(sources: Vec<Vars>) -> Settings {
+        Settings { sources }
+    }
+
+    /// The settings of the cli: the flags override the environment, which overrides the network
+    /// profile, which overrides the .env file. The .env file is read without being added to the
+    /// environment, so that it doesn't take precedence over the profile
+    pub fn load(flags: Vars, profile: Option<&NetworkProfile>) -> Settings {
+        let env = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        let dotenv = dotenv::dotenv_iter()
+            .map(|vars| vars.filter_map(Result::ok).collect())
+            .unwrap_or_default();
+        Settings::new(vec![
+            flags,
+            env,
+            profile.map(NetworkProfile::vars).unwrap_or_default(),
+            dotenv,
+        ])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.sources
+            .iter()
+            .find_map(|source| source.get(name))
+            .map(String::as_str)
+    }
+
+    /// Endpoints of the node from ADDR, a comma separated list
+    pub fn endpoints(&self) -> Option<Vec<String>> {
+        let endpoints = split_endpoints(self.get("ADDR")?);
+        (!endpoints.is_empty()).then_some(endpoints)
+    }
+
+    pub fn database(&self) -> Result<&str, Error> {
+        self.get("DATABASE_FILE").ok_or(Error::Config(
+            "No database configured, use --database, DATABASE_FILE or a network profile".to_owned(),
+        ))
+    }
+
+    /// The keystore directory from KEYSTORE_PATH, next to the database by default
+    pub fn keystore_path(&self) -> Result<PathBuf, Error> {
+        if let Some(path) = self.get("KEYSTORE_PATH") {
+            return Ok(PathBuf::from(path));
+        }
+        Ok(Path::new(self.database()?)
+            .parent()
+            .map(|dir| dir.join("keystore"))
+            .unwrap_or_else(|| PathBuf::from("keystore")))
+    }
+
+    /// The passphrase of the wallet vault from WALLET_PASSPHRASE, without it the passphrase is prompted for
+    pub fn passphrase(&self) -> Option<&str> {
+        self.get("WALLET_PASSPHRASE")
+    }
+
+    /// The denomination set with TOKEN_DECIMALS and TOKEN_SYMBOL, if any
+    pub fn denomination(&self) -> Result<Option<Denomination>, Error> {
+        let decimals = match self.get("TOKEN_DECIMALS") {
+            Some(decimals) => decimals,
+            None => return Ok(None),
+        };
+        let decimals = decimals
+            .parse()
+            .map_err(|_| Error::Config(format!("Invalid TOKEN_DECIMALS: {}", decimals)))?;
+        let symbol = self.get("TOKEN_SYMBOL").unwrap_or(DEFAULT_SYMBOL);
+        Denomination::new(decimals, symbol).map(Some)
+    }
+
+    /// ADMIN_SEED and ADMIN_ACCOUNT both set the admin, so they are read together from the first
+    /// source that sets either, a profile admin isn't overridden by a seed from .env
+    pub fn api_options(&self, verify: bool) -> Result<ApiOptions, Error> {
+        let admin = self.sources.iter().find(|source| {
+            source.contains_key("ADMIN_SEED") || source.contains_key("ADMIN_ACCOUNT")
+        });
+        Ok(ApiOptions {
+            admin_seed: admin.and_then(|source| source.get("ADMIN_SEED")).cloned(),
+            admin_account: admin
+                .and_then(|source| source.get("ADMIN_ACCOUNT"))
+                .cloned(),
+            denomination: self.denomination()?,
+            verify,
+        })
+    }
+}
+
+/// Config file holding the network profiles and the one in use
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Config {
+    pub current: Option<String>,
+    #[serde(default)]
+    pub networks: BTreeMap<String, NetworkProfile>,
+}
+
+impl Config {
+    /// config.toml in the wallet-cli directory of the user config dir
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .map(|dir| dir.join(APP_DIR))
+            .unwrap_or_default()
+            .join("config.toml")
+    }
+
+    /// Default database file for a network, in its own directory so that every network gets its own keystore
+    pub fn default_database(network: &str) -> PathBuf {
+        dirs::data_dir()
+            .map(|dir| dir.join(APP_DIR))
+            .unwrap_or_default()
+            .join(network)
+            .join("wallet.sql")
+    }
+
+    /// Loads the config, a missing file is an empty config
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let config = toml::from_str(&fs::read_to_string(path)?)?;
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The profile of the network given with --network, or of the network in use if none is given
+    pub fn profile(&self, network: Option<&str>) -> Result<Option<&NetworkProfile>, Error> {
+        let name = match network.or(self.current.as_deref()) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let profile = self
+            .networks
+            .get(name)
+            .ok_or(Error::Config(format!("Unknown network: {}", name)))?;
+        Ok(Some(profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn round_trips_profiles() {
+        let mut config = Config::default();
+        config.networks.insert(
+            "local".to_owned(),
+            NetworkProfile {
+                endpoint: "localhost:9944".to_owned(),
+                fallbacks: vec!["ws://localhost:9945".to_owned()],
+                database: "/tmp/local/wallet.sql".to_owned(),
+                token_decimals: Some(12),
+                token_symbol: Some("UNIT".to_owned()),
+                admin: None,
+            },
+        );
+        config.current = Some("local".to_owned());
+        let config: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        let profile = config.profile(None).unwrap().unwrap();
+        assert_eq!(
+            profile.endpoints(),
+            vec!["localhost:9944", "ws://localhost:9945"]
+        );
+        assert_eq!(profile.token_decimals, Some(12));
+        assert!(config.profile(Some("main")).is_err());
+        assert!(Config::default().profile(None).unwrap().is_none());
+    }
+
+    fn vars(values: &[(&str, &str)]) -> Vars {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn resolves_settings_from_the_first_source_setting_them() {
+        let profile = NetworkProfile {
+            endpoint: "ws://profile:9944".to_owned(),
+            fallbacks: Vec::new(),
+            database: "profile.sql".to_owned(),
+            token_decimals: Some(12),
+            token_symbol: None,
+            admin: Some("profile-admin".to_owned()),
+        };
+        let settings = Settings::new(vec![
+            vars(&[("ADDR", "ws://flag:9944, ws://flag:9945")]),
+            vars(&[("ADDR", "ws://env:9944"), ("TOKEN_SYMBOL", "ENV")]),
+            profile.vars(),
+            vars(&[
+                ("DATABASE_FILE", "dotenv.sql"),
+                ("ADMIN_SEED", "//Alice"),
+                ("WALLET_PASSPHRASE", "dotenv"),
+            ]),
+        ]);
+        assert_eq!(
+            settings.endpoints().unwrap(),
+            ["ws://flag:9944", "ws://flag:9945"]
+        );
+        assert_eq!(settings.database().unwrap(), "profile.sql");
+        assert_eq!(settings.keystore_path().unwrap(), Path::new("keystore"));
+        assert_eq!(settings.passphrase(), Some("dotenv"));
+        assert_eq!(
+            settings.denomination().unwrap(),
+            Some(Denomination::new(12, "ENV").unwrap())
+        );
+        // the admin of the profile isn't overridden by a seed from .env
+        let options = settings.api_options(false).unwrap();
+        assert_eq!(options.admin_account.as_deref(), Some("profile-admin"));
+        assert_eq!(options.admin_seed, None);
+
+        let settings = Settings::new(vec![vars(&[("ADMIN_SEED", "//Alice")]), profile.vars()]);
+        let options = settings.api_options(true).unwrap();
+        assert_eq!(options.admin_seed.as_deref(), Some("//Alice"));
+        assert_eq!(options.admin_account, None);
+        assert!(options.verify);
+
+        let settings = Settings::new(vec![vars(&[("ADDR", " , "), ("TOKEN_DECIMALS", "many")])]);
+        assert!(settings.endpoints().is_none());
+        assert!(matches!(settings.database(), Err(Error::Config(_))));
+        assert!(matches!(settings.keystore_path(), Err(Error::Config(_))));
+
+        let settings = Settings::new(vec![vars(&[("DATABASE_FILE", "/data/wallet.sql")])]);
+        assert_eq!(
+            settings.keystore_path().unwrap(),
+            Path::new("/data/keystore")
+        );
+        assert!(matches!(settings.denomination(), Err(Error::Config(_))));
+    }
+}
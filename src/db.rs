@@ -1,20 +1,96 @@
 use crate::error::Error;
+use crate::key_store::KeyStore;
+use crate::vault::{KdfParams, Vault};
+use log::info;
 use sqlite::{Connection, State, Statement, Value};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+// Schema changes applied in order on top of the base tables, the index of the last one applied
+// is tracked in the user_version pragma
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE accounts ADD COLUMN scheme TEXT NOT NULL DEFAULT 'sr25519';",
+    // watch-only accounts have no mnemonic nor scheme
+    "CREATE TABLE accounts_new(address TEXT, mnemonic TEXT NULL, scheme TEXT NULL,
+       CONSTRAINT accounts_pk PRIMARY KEY (address)
+     );
+     INSERT INTO accounts_new(address, mnemonic, scheme) SELECT address, mnemonic, scheme FROM accounts;
+     DROP TABLE accounts;
+     ALTER TABLE accounts_new RENAME TO accounts;",
+    "ALTER TABLE accounts ADD COLUMN label TEXT NULL;
+     CREATE UNIQUE INDEX accounts_label ON accounts(label);",
+    // amounts are stored as text as they don't fit in an sqlite integer
+    "CREATE TABLE transactions(hash TEXT, signer TEXT, call TEXT, counterparty TEXT NULL,
+       amount TEXT NULL, fee INTEGER, submitted_at TEXT, status TEXT, block_hash TEXT NULL,
+       CONSTRAINT transactions_pk PRIMARY KEY (hash)
+     );
+     CREATE INDEX transactions_submitted_at ON transactions(submitted_at);",
+    // entries found by the indexer that weren't submitted locally are timestamped when first seen
+    "ALTER TABLE transactions ADD COLUMN block_number INTEGER NULL;
+     CREATE TABLE indexer(next_block INTEGER);",
+    // the indexer keeps a cursor per account, so that accounts added later are scanned from genesis
+    "CREATE TABLE index_cursors(address TEXT, next_block INTEGER,
+       CONSTRAINT index_cursors_pk PRIMARY KEY (address)
+     );
+     INSERT INTO index_cursors(address, next_block)
+       SELECT accounts.address, indexer.next_block FROM accounts, indexer;
+     DROP TABLE indexer;",
+];
+
 pub struct DB {
     conn: Connection,
 }
 
+pub struct Account {
+    pub address: String,
+    pub mnemonic: Option<String>,
+    pub scheme: Option<String>,
+}
+
+impl Account {
+    pub fn is_watch_only(&self) -> bool {
+        self.mnemonic.is_none()
+    }
+}
+
+pub struct AccountSummary {
+    pub address: String,
+    pub label: Option<String>,
+    pub watch_only: bool,
+}
+
+/// Entry of the ledger of submitted transactions and of transactions found by the indexer
+pub struct TransactionRecord {
+    pub hash: String,
+    pub signer: String,
+    pub call: String,
+    pub counterparty: Option<String>,
+    pub amount: Option<u128>,
+    pub fee: u32,
+    pub submitted_at: String,
+    pub status: String,
+    pub block_hash: Option<String>,
+    pub block_number: Option<u64>,
+}
+
+/// Criteria to select ledger entries, unset criteria match every entry.
+/// Dates are compared against the submission time, since inclusive and until exclusive
+#[derive(Default)]
+pub struct TransactionFilter {
+    pub account: Option<String>,
+    pub call: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
 impl DB {
     pub fn new(db_file: &str) -> Result<DB, Error> {
         let path = Path::new(db_file);
         if !path.exists() {
-            fs::create_dir_all(
-                path.parent()
-                    .ok_or(Error::new("Directory for the db file wasn't specified"))?,
-            )?;
+            fs::create_dir_all(path.parent().ok_or(Error::Config(
+                "Directory for the db file wasn't specified".to_owned(),
+            ))?)?;
         }
         let conn = sqlite::open(path)?;
         let query = "
@@ -24,57 +100,329 @@ impl DB {
         CREATE TABLE IF NOT EXISTS accounts(address TEXT, mnemonic TEXT,
           CONSTRAINT accounts_pk PRIMARY KEY (address)
         );
+        CREATE TABLE IF NOT EXISTS vault(salt TEXT, m_cost INTEGER, t_cost INTEGER, p_cost INTEGER, check_value TEXT);
       ";
         conn.execute(query)?;
+        let db = DB { conn };
+        db.migrate()?;
+        Ok(db)
+    }
+
+    fn migrate(&self) -> Result<(), Error> {
+        self.apply_migrations(MIGRATIONS)
+    }
 
-        Ok(DB { conn })
+    /// Applies the migrations past the user_version, each in its own transaction so that a failed
+    /// one leaves the database as the previous one left it
+    fn apply_migrations(&self, migrations: &[&str]) -> Result<(), Error> {
+        let mut statement = self.conn.prepare("PRAGMA user_version")?;
+        let mut version = 0;
+        while let Ok(State::Row) = statement.next() {
+            version = statement.read::<i64, _>(0)? as usize;
+        }
+        for (index, migration) in migrations.iter().enumerate().skip(version) {
+            self.conn.execute("BEGIN TRANSACTION;")?;
+            let applied = self.conn.execute(format!(
+                "{} PRAGMA user_version = {};",
+                migration,
+                index + 1
+            ));
+            if let Err(err) = applied {
+                // sqlite already rolled back after some errors, then there is no transaction to end
+                let _ = self.conn.execute("ROLLBACK;");
+                return Err(err.into());
+            }
+            self.conn.execute("COMMIT;")?;
+        }
+        Ok(())
     }
 
-    pub fn add_account(&self, address: &str, mnemonic: &str) -> Result<(), Error> {
+    pub fn has_vault(&self) -> Result<bool, Error> {
+        Ok(self.find_vault()?.is_some())
+    }
+
+    fn find_vault(&self) -> Result<Option<(KdfParams, String)>, Error> {
+        let query = "SELECT salt, m_cost, t_cost, p_cost, check_value from vault";
+        let mut statement = self.conn.prepare(query)?;
+        while let Ok(State::Row) = statement.next() {
+            let params = KdfParams {
+                salt: hex::decode(statement.read::<String, _>("salt")?)?,
+                m_cost: statement.read::<i64, _>("m_cost")? as u32,
+                t_cost: statement.read::<i64, _>("t_cost")? as u32,
+                p_cost: statement.read::<i64, _>("p_cost")? as u32,
+            };
+            return Ok(Some((params, statement.read::<String, _>("check_value")?)));
+        }
+        Ok(None)
+    }
+
+    /// Derives the vault key from the passphrase and verifies it against the stored check value
+    pub fn unlock_vault(&self, passphrase: &str) -> Result<Vault, Error> {
+        let (params, check_value) = self.find_vault()?.ok_or(Error::Database(
+            "Wallet is not encrypted, run encrypt-wallet first".to_owned(),
+        ))?;
+        let vault = Vault::new(passphrase, &params)?;
+        vault.verify_check_value(&check_value)?;
+        Ok(vault)
+    }
+
+    /// Creates the vault and encrypts any plaintext mnemonics stored before the vault existed,
+    /// returns the vault and the number of accounts migrated
+    pub fn init_vault(&self, passphrase: &str) -> Result<(Vault, usize), Error> {
+        if self.has_vault()? {
+            return Err(Error::Database("Wallet is already encrypted".to_owned()));
+        }
+        let params = KdfParams::generate();
+        let vault = Vault::new(passphrase, &params)?;
+        let plaintext_accounts = self.get_raw_accounts()?;
+        // overwritten values are zeroed instead of being left in free pages
+        self.conn.execute("PRAGMA secure_delete = ON;")?;
+        self.conn.execute("BEGIN TRANSACTION;")?;
+        if let Err(err) = self.store_vault(&vault, &params, &plaintext_accounts) {
+            self.conn.execute("ROLLBACK;")?;
+            return Err(err);
+        }
+        self.conn.execute("COMMIT;")?;
+        // rebuilds the file, so that no page written before the migration keeps a plaintext mnemonic
+        self.conn.execute("VACUUM;")?;
+        Ok((vault, plaintext_accounts.len()))
+    }
+
+    /// Unlocks the vault, creating it the first time, see [`DB::init_vault`]. The passphrase is asked
+    /// for with a prompt, a new one is asked for twice when confirm is set
+    pub fn open_vault(
+        &self,
+        mut passphrase: impl FnMut(&str) -> Result<String, Error>,
+        confirm: bool,
+    ) -> Result<Vault, Error> {
+        if self.has_vault()? {
+            return self.unlock_vault(&passphrase("Wallet passphrase: ")?);
+        }
+        let new = passphrase("New wallet passphrase: ")?;
+        if confirm && new != passphrase("Confirm wallet passphrase: ")? {
+            return Err(Error::Keystore("Passphrases do not match".to_owned()));
+        }
+        let (vault, migrated) = self.init_vault(&new)?;
+        info!("Wallet encrypted, {} existing accounts migrated", migrated);
+        Ok(vault)
+    }
+
+    fn store_vault(
+        &self,
+        vault: &Vault,
+        params: &KdfParams,
+        plaintext_accounts: &[(String, String)],
+    ) -> Result<(), Error> {
+        for (address, mnemonic) in plaintext_accounts {
+            let query = "UPDATE accounts SET mnemonic = :mnemonic WHERE address = :address";
+            let mut statement = self.conn.prepare(query)?;
+            statement.bind::<&[(&str, Value)]>(
+                &[
+                    (":address", address.as_str().into()),
+                    (":mnemonic", vault.encrypt(mnemonic)?.into()),
+                ][..],
+            )?;
+            Self::execute_statement(&mut statement)?;
+        }
         let query = "
-      INSERT INTO accounts(address, mnemonic) 
-      VALUES(:address,:mnemonic)
+      INSERT INTO vault(salt, m_cost, t_cost, p_cost, check_value)
+      VALUES(:salt,:m_cost,:t_cost,:p_cost,:check_value)
       ";
         let mut statement = self.conn.prepare(query)?;
         statement.bind::<&[(&str, Value)]>(
-            &[(":address", address.into()), (":mnemonic", mnemonic.into())][..],
+            &[
+                (":salt", hex::encode(&params.salt).into()),
+                (":m_cost", (params.m_cost as i64).into()),
+                (":t_cost", (params.t_cost as i64).into()),
+                (":p_cost", (params.p_cost as i64).into()),
+                (":check_value", vault.encrypt_check_value()?.into()),
+            ][..],
         )?;
-        Self::execute_prepared_statement(&mut statement);
-        self.set_default_account(address)?;
+        Self::execute_statement(&mut statement)?;
         Ok(())
     }
 
-    pub fn execute_prepared_statement(statement: &mut Statement) {
-        while let Ok(State::Row) = statement.next() {}
+    fn get_raw_accounts(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut accounts = Vec::new();
+        let query = "SELECT address, mnemonic from accounts WHERE mnemonic IS NOT NULL";
+        let mut statement = self.conn.prepare(query)?;
+        while let Ok(State::Row) = statement.next() {
+            accounts.push((
+                statement.read::<String, _>("address")?,
+                statement.read::<String, _>("mnemonic")?,
+            ));
+        }
+        Ok(accounts)
     }
 
-    pub fn get_accounts(&self) -> Result<Vec<String>, Error> {
+    pub fn add_account(
+        &self,
+        vault: &Vault,
+        address: &str,
+        mnemonic: &str,
+        scheme: &str,
+    ) -> Result<(), Error> {
+        let query = "
+      INSERT INTO accounts(address, mnemonic, scheme) 
+      VALUES(:address,:mnemonic,:scheme)
+      ";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind::<&[(&str, Value)]>(
+            &[
+                (":address", address.into()),
+                (":mnemonic", vault.encrypt(mnemonic)?.into()),
+                (":scheme", scheme.into()),
+            ][..],
+        )?;
+        Self::execute_statement(&mut statement)?;
+        self.set_default_account(vault, address)?;
+        Ok(())
+    }
+
+    /// Adds an account that is tracked by address only, it can't be used to sign transactions
+    pub fn add_watch_account(&self, address: &str) -> Result<(), Error> {
+        if self.account_exists(address)? {
+            return Err(Error::Database(format!(
+                "Account: {} is already in the wallet",
+                address
+            )));
+        }
+        let query = "
+      INSERT INTO accounts(address, mnemonic, scheme) 
+      VALUES(:address,:mnemonic,:scheme)
+      ";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind::<&[(&str, Value)]>(
+            &[
+                (":address", address.into()),
+                (":mnemonic", Value::Null),
+                (":scheme", Value::Null),
+            ][..],
+        )?;
+        Self::execute_statement(&mut statement)?;
+        Ok(())
+    }
+
+    /// Steps the statement until it is done, failing on the first error
+    fn execute_statement(statement: &mut Statement) -> Result<(), Error> {
+        while let State::Row = statement.next()? {}
+        Ok(())
+    }
+
+    pub fn get_accounts(&self) -> Result<Vec<AccountSummary>, Error> {
         let mut accounts = Vec::new();
-        let query = "SELECT address from accounts";
+        let query = "SELECT address, label, mnemonic IS NULL AS watch_only from accounts";
         let mut statement = self.conn.prepare(query)?;
         while let Ok(State::Row) = statement.next() {
-            accounts.push(statement.read::<String, _>("address")?);
+            accounts.push(AccountSummary {
+                address: statement.read::<String, _>("address")?,
+                label: statement.read::<Option<String>, _>("label")?,
+                watch_only: statement.read::<i64, _>("watch_only")? == 1,
+            });
         }
         Ok(accounts)
     }
 
-    pub fn find_account(&self, address: &str) -> Result<Option<(String, String)>, Error> {
-        let query = "SELECT address, mnemonic from accounts where address = :address";
+    pub fn account_exists(&self, address: &str) -> Result<bool, Error> {
+        let query = "SELECT address from accounts where address = :address";
         let mut statement = self.conn.prepare(query)?;
         statement.bind((":address", address))?;
+        Ok(matches!(statement.next(), Ok(State::Row)))
+    }
+
+    pub fn find_address_by_label(&self, label: &str) -> Result<Option<String>, Error> {
+        let query = "SELECT address from accounts where label = :label";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind((":label", label))?;
         while let Ok(State::Row) = statement.next() {
-            return Ok(Some((
-                address.to_owned(),
-                statement.read::<String, _>("mnemonic")?,
-            )));
+            return Ok(Some(statement.read::<String, _>("address")?));
         }
         Ok(None)
     }
 
-    pub fn get_account(&self, address: &str) -> Result<(String, String), Error> {
+    /// Resolves an account given either as the label of a stored account or as an address
+    pub fn resolve_account(&self, account: &str) -> Result<String, Error> {
+        if let Some(address) = self.find_address_by_label(account)? {
+            return Ok(address);
+        }
+        if let Err(Error::Address { source, .. }) = KeyStore::get_pub_key(account) {
+            return Err(Error::Address {
+                message: format!("{} is neither a known label nor a valid address", account),
+                source,
+            });
+        }
+        Ok(account.to_owned())
+    }
+
+    /// Labels the account, a label can't be an address as it would shadow it when resolving accounts
+    pub fn set_label(&self, address: &str, label: &str) -> Result<(), Error> {
+        if KeyStore::get_pub_key(label).is_ok() {
+            return Err(Error::Config(
+                "A label can not be a valid address".to_owned(),
+            ));
+        }
+        if !self.account_exists(address)? {
+            return Err(Error::Database("Account not found".to_owned()));
+        }
+        if let Some(labeled) = self.find_address_by_label(label)? {
+            if labeled != address {
+                return Err(Error::Database(format!(
+                    "Label: {} is already used by account: {}",
+                    label, labeled
+                )));
+            }
+        }
+        self.update_label(address, label.into())
+    }
+
+    pub fn clear_label(&self, address: &str) -> Result<(), Error> {
+        if !self.account_exists(address)? {
+            return Err(Error::Database("Account not found".to_owned()));
+        }
+        self.update_label(address, Value::Null)
+    }
+
+    fn update_label(&self, address: &str, label: Value) -> Result<(), Error> {
+        let query = "UPDATE accounts SET label = :label WHERE address = :address";
+        let mut statement = self.conn.prepare(query)?;
+        statement
+            .bind::<&[(&str, Value)]>(&[(":address", address.into()), (":label", label)][..])?;
+        Self::execute_statement(&mut statement)?;
+        Ok(())
+    }
+
+    pub fn is_watch_only(&self, address: &str) -> Result<bool, Error> {
+        let query = "SELECT mnemonic IS NULL AS watch_only from accounts where address = :address";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind((":address", address))?;
+        while let Ok(State::Row) = statement.next() {
+            return Ok(statement.read::<i64, _>("watch_only")? == 1);
+        }
+        Ok(false)
+    }
+
+    pub fn find_account(&self, vault: &Vault, address: &str) -> Result<Option<Account>, Error> {
+        let query = "SELECT address, mnemonic, scheme from accounts where address = :address";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind((":address", address))?;
+        while let Ok(State::Row) = statement.next() {
+            let mnemonic = match statement.read::<Option<String>, _>("mnemonic")? {
+                Some(encrypted) => Some(vault.decrypt(&encrypted)?),
+                None => None,
+            };
+            return Ok(Some(Account {
+                address: address.to_owned(),
+                mnemonic,
+                scheme: statement.read::<Option<String>, _>("scheme")?,
+            }));
+        }
+        Ok(None)
+    }
+
+    pub fn get_account(&self, vault: &Vault, address: &str) -> Result<Account, Error> {
         let account = self
-            .find_account(address)?
-            .ok_or(Error::new("Account not found"))?;
+            .find_account(vault, address)?
+            .ok_or(Error::Database("Account not found".to_owned()))?;
         Ok(account)
     }
 
@@ -90,18 +438,12 @@ impl DB {
     pub fn get_default_account(&self) -> Result<String, Error> {
         let account = self
             .find_default_account()?
-            .ok_or(Error::new("Default account not set"))?;
-        Ok(account)
-    }
-
-    pub fn get_default_mnemonic(&self) -> Result<(String, String), Error> {
-        let account = self.get_default_account()?;
-        let account = self.get_account(&account)?;
+            .ok_or(Error::Database("Default account not set".to_owned()))?;
         Ok(account)
     }
 
-    pub fn set_default_account(&self, address: &str) -> Result<(), Error> {
-        self.get_account(address)?;
+    pub fn set_default_account(&self, vault: &Vault, address: &str) -> Result<(), Error> {
+        self.get_account(vault, address)?;
         let query = format!(
             "
       DELETE FROM config;
@@ -113,4 +455,482 @@ impl DB {
         self.conn.execute(query)?;
         Ok(())
     }
+
+    /// Records a submitted transaction, resubmitting an extrinsic replaces its previous entry
+    pub fn add_transaction(&self, record: &TransactionRecord) -> Result<(), Error> {
+        let query = "
+      INSERT OR REPLACE INTO transactions(hash, signer, call, counterparty, amount, fee, submitted_at, status, block_hash, block_number)
+      VALUES(:hash,:signer,:call,:counterparty,:amount,:fee,:submitted_at,:status,:block_hash,:block_number)
+      ";
+        self.execute_transaction_statement(query, record)
+    }
+
+    /// Records the transactions found in a block and moves the cursors of the accounts it was scanned
+    /// for past it, atomically. Transactions already in the ledger keep their submission time
+    pub fn add_indexed_transactions(
+        &self,
+        records: &[TransactionRecord],
+        accounts: &[String],
+        next_block: u64,
+    ) -> Result<(), Error> {
+        self.conn.execute("BEGIN TRANSACTION;")?;
+        if let Err(err) = self.store_indexed_transactions(records, accounts, next_block) {
+            self.conn.execute("ROLLBACK;")?;
+            return Err(err);
+        }
+        self.conn.execute("COMMIT;")?;
+        Ok(())
+    }
+
+    fn store_indexed_transactions(
+        &self,
+        records: &[TransactionRecord],
+        accounts: &[String],
+        next_block: u64,
+    ) -> Result<(), Error> {
+        let query = "
+      INSERT INTO transactions(hash, signer, call, counterparty, amount, fee, submitted_at, status, block_hash, block_number)
+      VALUES(:hash,:signer,:call,:counterparty,:amount,:fee,:submitted_at,:status,:block_hash,:block_number)
+      ON CONFLICT(hash) DO UPDATE SET
+        status = excluded.status, block_hash = excluded.block_hash, block_number = excluded.block_number
+      ";
+        for record in records {
+            self.execute_transaction_statement(query, record)?;
+        }
+        let query = "INSERT OR REPLACE INTO index_cursors(address, next_block) VALUES(:address, :next_block)";
+        for account in accounts {
+            let mut statement = self.conn.prepare(query)?;
+            statement.bind::<&[(&str, Value)]>(
+                &[
+                    (":address", account.as_str().into()),
+                    (":next_block", (next_block as i64).into()),
+                ][..],
+            )?;
+            Self::execute_statement(&mut statement)?;
+        }
+        Ok(())
+    }
+
+    /// The first block the indexer hasn't processed yet for every wallet account, genesis for the
+    /// accounts it never ran for
+    pub fn get_index_cursors(&self) -> Result<HashMap<String, u64>, Error> {
+        let query = "SELECT accounts.address, COALESCE(index_cursors.next_block, 0) AS next_block
+          FROM accounts LEFT JOIN index_cursors ON index_cursors.address = accounts.address";
+        let mut statement = self.conn.prepare(query)?;
+        let mut cursors = HashMap::new();
+        while let Ok(State::Row) = statement.next() {
+            cursors.insert(
+                statement.read::<String, _>("address")?,
+                statement.read::<i64, _>("next_block")? as u64,
+            );
+        }
+        Ok(cursors)
+    }
+
+    fn execute_transaction_statement(
+        &self,
+        query: &str,
+        record: &TransactionRecord,
+    ) -> Result<(), Error> {
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind::<&[(&str, Value)]>(
+            &[
+                (":hash", record.hash.as_str().into()),
+                (":signer", record.signer.as_str().into()),
+                (":call", record.call.as_str().into()),
+                (":counterparty", Self::optional(record.counterparty.clone())),
+                (
+                    ":amount",
+                    Self::optional(record.amount.map(|amount| amount.to_string())),
+                ),
+                (":fee", (record.fee as i64).into()),
+                (":submitted_at", record.submitted_at.as_str().into()),
+                (":status", record.status.as_str().into()),
+                (":block_hash", Self::optional(record.block_hash.clone())),
+                (
+                    ":block_number",
+                    record
+                        .block_number
+                        .map(|number| Value::from(number as i64))
+                        .unwrap_or(Value::Null),
+                ),
+            ][..],
+        )?;
+        Self::execute_statement(&mut statement)?;
+        Ok(())
+    }
+
+    pub fn update_transaction_status(
+        &self,
+        hash: &str,
+        status: &str,
+        block_hash: Option<&str>,
+    ) -> Result<(), Error> {
+        let query =
+            "UPDATE transactions SET status = :status, block_hash = :block_hash WHERE hash = :hash";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind::<&[(&str, Value)]>(
+            &[
+                (":hash", hash.into()),
+                (":status", status.into()),
+                (":block_hash", Self::optional(block_hash.map(str::to_owned))),
+            ][..],
+        )?;
+        Self::execute_statement(&mut statement)?;
+        Ok(())
+    }
+
+    /// The status of a transaction in the ledger along with the block it was included in
+    pub fn find_transaction_status(
+        &self,
+        hash: &str,
+    ) -> Result<Option<(String, Option<String>)>, Error> {
+        let query = "SELECT status, block_hash from transactions where hash = :hash";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind((":hash", hash))?;
+        while let Ok(State::Row) = statement.next() {
+            return Ok(Some((
+                statement.read::<String, _>("status")?,
+                statement.read::<Option<String>, _>("block_hash")?,
+            )));
+        }
+        Ok(None)
+    }
+
+    /// Ledger entries matching the filter, oldest first
+    pub fn get_transactions(
+        &self,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<TransactionRecord>, Error> {
+        let mut transactions = Vec::new();
+        let query = "
+      SELECT hash, signer, call, counterparty, amount, fee, submitted_at, status, block_hash, block_number FROM transactions
+      WHERE (:account IS NULL OR signer = :account OR counterparty = :account)
+        AND (:call IS NULL OR call = :call)
+        AND (:since IS NULL OR submitted_at >= :since)
+        AND (:until IS NULL OR submitted_at < :until)
+      ORDER BY submitted_at, block_number
+      ";
+        let mut statement = self.conn.prepare(query)?;
+        statement.bind::<&[(&str, Value)]>(
+            &[
+                (":account", Self::optional(filter.account.clone())),
+                (":call", Self::optional(filter.call.clone())),
+                (":since", Self::optional(filter.since.clone())),
+                (":until", Self::optional(filter.until.clone())),
+            ][..],
+        )?;
+        while let Ok(State::Row) = statement.next() {
+            let amount = match statement.read::<Option<String>, _>("amount")? {
+                Some(amount) => Some(amount.parse::<u128>().map_err(|_| {
+                    Error::Database("Invalid amount stored in the ledger".to_owned())
+                })?),
+                None => None,
+            };
+            transactions.push(TransactionRecord {
+                hash: statement.read::<String, _>("hash")?,
+                signer: statement.read::<String, _>("signer")?,
+                call: statement.read::<String, _>("call")?,
+                counterparty: statement.read::<Option<String>, _>("counterparty")?,
+                amount,
+                fee: statement.read::<i64, _>("fee")? as u32,
+                submitted_at: statement.read::<String, _>("submitted_at")?,
+                status: statement.read::<String, _>("status")?,
+                block_hash: statement.read::<Option<String>, _>("block_hash")?,
+                block_number: statement
+                    .read::<Option<i64>, _>("block_number")?
+                    .map(|number| number as u64),
+            });
+        }
+        Ok(transactions)
+    }
+
+    fn optional(value: Option<String>) -> Value {
+        value.map(Value::from).unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tempfile::TempDir;
+
+    const MNEMONIC: &str = "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+
+    #[test]
+    fn init_vault_leaves_no_plaintext_mnemonic_in_the_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("wallet.sql");
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        // accounts stored before the vault existed kept their mnemonic in plaintext
+        for index in 0..50 {
+            let query = "INSERT INTO accounts(address, mnemonic) VALUES(:address, :mnemonic)";
+            let mut statement = db.conn.prepare(query).unwrap();
+            statement
+                .bind::<&[(&str, Value)]>(
+                    &[
+                        (":address", format!("address-{}", index).into()),
+                        (":mnemonic", format!("{}//{}", MNEMONIC, index).into()),
+                    ][..],
+                )
+                .unwrap();
+            DB::execute_statement(&mut statement).unwrap();
+        }
+        assert!(contains(&fs::read(&path).unwrap(), MNEMONIC));
+
+        let (vault, migrated) = db.init_vault("passphrase").unwrap();
+        assert_eq!(migrated, 50);
+        drop(db);
+        assert!(!contains(&fs::read(&path).unwrap(), MNEMONIC));
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        let account = db.get_account(&vault, "address-7").unwrap();
+        assert_eq!(account.mnemonic.unwrap(), format!("{}//7", MNEMONIC));
+    }
+
+    #[test]
+    fn opens_the_vault_with_the_passphrase_asked_for() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::new(dir.path().join("wallet.sql").to_str().unwrap()).unwrap();
+        let mut answers = vec!["second", "first"];
+        let mut prompts = Vec::new();
+        let mut ask = |prompt: &str| {
+            prompts.push(prompt.to_owned());
+            Ok(answers.pop().unwrap_or("passphrase").to_owned())
+        };
+        assert!(matches!(
+            db.open_vault(&mut ask, true),
+            Err(Error::Keystore(_))
+        ));
+        assert!(!db.has_vault().unwrap());
+
+        db.open_vault(&mut ask, true).unwrap();
+        assert!(db.has_vault().unwrap());
+        db.open_vault(&mut ask, true).unwrap();
+        assert_eq!(
+            prompts,
+            [
+                "New wallet passphrase: ",
+                "Confirm wallet passphrase: ",
+                "New wallet passphrase: ",
+                "Confirm wallet passphrase: ",
+                "Wallet passphrase: "
+            ]
+        );
+        assert!(matches!(
+            db.open_vault(|_| Ok("wrong".to_owned()), false),
+            Err(Error::Keystore(_))
+        ));
+    }
+
+    #[test]
+    fn a_failed_vault_write_is_rolled_back() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::new(dir.path().join("wallet.sql").to_str().unwrap()).unwrap();
+        db.conn
+            .execute(format!(
+                "INSERT INTO accounts(address, mnemonic) VALUES('address-1', '{}');
+                 CREATE TRIGGER vault_full BEFORE INSERT ON vault BEGIN SELECT RAISE(ABORT, 'disk full'); END;",
+                MNEMONIC
+            ))
+            .unwrap();
+
+        assert!(matches!(db.init_vault("passphrase"), Err(Error::Sqlite(_))));
+        assert!(!db.has_vault().unwrap());
+        // the mnemonics encrypted before the failure are left as they were
+        assert_eq!(
+            db.get_raw_accounts().unwrap(),
+            [("address-1".to_owned(), MNEMONIC.to_owned())]
+        );
+
+        db.conn.execute("DROP TRIGGER vault_full;").unwrap();
+        let (vault, migrated) = db.init_vault("passphrase").unwrap();
+        assert_eq!(migrated, 1);
+        let account = db.get_account(&vault, "address-1").unwrap();
+        assert_eq!(account.mnemonic.unwrap(), MNEMONIC);
+    }
+
+    #[test]
+    fn returns_the_errors_of_ledger_writes() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::new(dir.path().join("wallet.sql").to_str().unwrap()).unwrap();
+        let record = |hash: &str| TransactionRecord {
+            hash: hash.to_owned(),
+            signer: "address-1".to_owned(),
+            call: "transfer".to_owned(),
+            counterparty: Some("address-2".to_owned()),
+            amount: Some(5),
+            fee: 10,
+            submitted_at: "2023-01-01T00:00:00Z".to_owned(),
+            status: "submitted".to_owned(),
+            block_hash: None,
+            block_number: None,
+        };
+        db.add_watch_account("address-1").unwrap();
+        db.add_transaction(&record("0x01")).unwrap();
+        db.conn
+            .execute(
+                "CREATE TRIGGER ledger_full BEFORE INSERT ON transactions BEGIN SELECT RAISE(ABORT, 'disk full'); END;
+                 CREATE TRIGGER ledger_locked BEFORE UPDATE ON transactions BEGIN SELECT RAISE(ABORT, 'locked'); END;",
+            )
+            .unwrap();
+
+        assert!(matches!(
+            db.add_transaction(&record("0x02")),
+            Err(Error::Sqlite(_))
+        ));
+        assert!(matches!(
+            db.update_transaction_status("0x01", "in-block", Some("0x0b")),
+            Err(Error::Sqlite(_))
+        ));
+        assert_eq!(
+            db.find_transaction_status("0x01").unwrap(),
+            Some(("submitted".to_owned(), None))
+        );
+        // the cursor doesn't move past a block whose transactions weren't recorded
+        assert!(db
+            .add_indexed_transactions(&[record("0x03")], &["address-1".to_owned()], 5)
+            .is_err());
+        assert_eq!(db.get_index_cursors().unwrap()["address-1"], 0);
+    }
+
+    #[test]
+    fn migrates_a_database_of_the_base_schema() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("wallet.sql");
+        let conn = sqlite::open(&path).unwrap();
+        conn.execute(
+            "CREATE TABLE accounts(address TEXT, mnemonic TEXT, CONSTRAINT accounts_pk PRIMARY KEY (address));
+             INSERT INTO accounts(address, mnemonic) VALUES('address-1', 'mnemonic');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let db = DB::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(number(&db, "PRAGMA user_version"), MIGRATIONS.len() as i64);
+        assert_eq!(
+            number(
+                &db,
+                "SELECT COUNT(*) FROM accounts WHERE scheme = 'sr25519' AND label IS NULL"
+            ),
+            1
+        );
+        drop(db);
+        // migrations already applied are not applied again
+        DB::new(path.to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_failed_migration_is_rolled_back() {
+        let db = DB {
+            conn: sqlite::open(":memory:").unwrap(),
+        };
+        let first = "CREATE TABLE a(x INTEGER);";
+        let second = "INSERT INTO a VALUES(1);";
+        let failing = "CREATE TABLE b(y INTEGER); INSERT INTO missing VALUES(2);";
+        assert!(db.apply_migrations(&[first, second, failing]).is_err());
+        assert_eq!(number(&db, "PRAGMA user_version"), 2);
+        assert_eq!(
+            number(&db, "SELECT COUNT(*) FROM sqlite_master WHERE name = 'b'"),
+            0
+        );
+        // no transaction was left open
+        db.conn.execute("BEGIN TRANSACTION; COMMIT;").unwrap();
+
+        // the migrations resume after the last applied one, in order
+        let fixed = "CREATE TABLE b(y INTEGER); INSERT INTO b SELECT x + 1 FROM a;";
+        db.apply_migrations(&[first, second, fixed]).unwrap();
+        assert_eq!(number(&db, "PRAGMA user_version"), 3);
+        assert_eq!(number(&db, "SELECT COUNT(*) FROM a"), 1);
+        assert_eq!(number(&db, "SELECT y FROM b"), 2);
+    }
+
+    #[test]
+    fn tells_watch_only_accounts_apart() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::new(dir.path().join("wallet.sql").to_str().unwrap()).unwrap();
+        let (watched, stored) = ("address-1", "address-2");
+        db.add_watch_account(watched).unwrap();
+        db.conn
+            .execute(format!(
+                "INSERT INTO accounts(address, mnemonic, scheme) VALUES('{}', 'encrypted', 'sr25519')",
+                stored
+            ))
+            .unwrap();
+
+        assert!(db.is_watch_only(watched).unwrap());
+        assert!(!db.is_watch_only(stored).unwrap());
+        assert!(!db.is_watch_only("unknown").unwrap());
+        let accounts = db.get_accounts().unwrap();
+        let summary = |address: &str| {
+            accounts
+                .iter()
+                .find(|a| a.address == address)
+                .unwrap()
+                .watch_only
+        };
+        assert!(summary(watched));
+        assert!(!summary(stored));
+
+        // an account already in the wallet isn't turned into a watch-only one, nor added twice
+        for address in [watched, stored] {
+            assert!(matches!(
+                db.add_watch_account(address),
+                Err(Error::Database(_))
+            ));
+        }
+        assert!(!db.is_watch_only(stored).unwrap());
+        assert_eq!(db.get_accounts().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn resolves_labels_and_addresses() {
+        let dir = TempDir::new().unwrap();
+        let db = DB::new(dir.path().join("wallet.sql").to_str().unwrap()).unwrap();
+        let (bob, dave) = (
+            "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty",
+            "5DAAnrj7VHTznn2AWBemMuyBwZWs6FNFjdyVXUeYum3PTXFy",
+        );
+        db.add_watch_account(bob).unwrap();
+        db.set_label(bob, "savings").unwrap();
+        assert_eq!(db.resolve_account("savings").unwrap(), bob);
+        // addresses resolve to themselves, stored or not
+        assert_eq!(db.resolve_account(bob).unwrap(), bob);
+        assert_eq!(db.resolve_account(dave).unwrap(), dave);
+        assert!(matches!(
+            db.resolve_account("unknown"),
+            Err(Error::Address { .. })
+        ));
+
+        // a label belongs to a single account, and only stored accounts get one
+        db.add_watch_account(dave).unwrap();
+        assert!(matches!(
+            db.set_label(dave, "savings"),
+            Err(Error::Database(_))
+        ));
+        db.set_label(bob, "savings").unwrap();
+        assert!(matches!(
+            db.set_label("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY", "other"),
+            Err(Error::Database(_))
+        ));
+        assert!(matches!(db.set_label(dave, bob), Err(Error::Config(_))));
+
+        db.clear_label(bob).unwrap();
+        assert!(db.resolve_account("savings").is_err());
+        db.set_label(dave, "savings").unwrap();
+        assert_eq!(db.resolve_account("savings").unwrap(), dave);
+    }
+
+    fn number(db: &DB, query: &str) -> i64 {
+        let mut statement = db.conn.prepare(query).unwrap();
+        assert!(matches!(statement.next().unwrap(), State::Row));
+        statement.read::<i64, _>(0).unwrap()
+    }
+
+    fn contains(bytes: &[u8], text: &str) -> bool {
+        bytes
+            .windows(text.len())
+            .any(|window| window == text.as_bytes())
+    }
 }
@@ -1,74 +1,333 @@
+use crate::types::Error as RuntimeError;
+use jsonrpsee_types::error::CallError;
 use std::fmt;
 
+/// Failures of the wallet, each variant belongs to a category with its own exit code so that
+/// scripts can tell for instance an unreachable node from a rejected transaction
 #[derive(Debug)]
-pub struct Error {
-    details: String,
+pub enum Error {
+    /// Missing or invalid settings, arguments or input files
+    Config(String),
+    ConfigFile(toml::de::Error),
+    ConfigWrite(toml::ser::Error),
+    Date(chrono::ParseError),
+    Csv(csv::Error),
+    /// A local file can't be read or written
+    Io(std::io::Error),
+    /// The wallet database failed, or doesn't hold what was asked for
+    Database(String),
+    Sqlite(sqlite::Error),
+    /// Keys can't be stored, unlocked or used for signing
+    Keystore(String),
+    KeystoreBackend(sp_keystore::Error),
+    Secret(sp_core::crypto::SecretStringError),
+    Kdf(argon2::Error),
+    Cipher(chacha20poly1305::Error),
+    /// An argument is not a valid ss58 address
+    Address {
+        message: String,
+        source: sp_core::crypto::PublicError,
+    },
+    /// The node can't be reached, or its answer can't be used
+    Node(String),
+    /// A failed call, data holds what the node attached to the error, for rejected transactions the validity error
+    Rpc {
+        source: jsonrpsee_core::Error,
+        data: Option<String>,
+    },
+    /// The transaction was submitted but didn't reach the expected outcome
+    Transaction(String),
+    /// The runtime rejected the transaction, or would reject it
+    Runtime(RuntimeError),
+    /// Data read from the node or from a file can't be decoded
+    Decode(String),
+    Codec(parity_scale_codec::Error),
+    Hex(hex::FromHexError),
+    Blob(sp_maybe_compressed_blob::Error),
+}
+
+/// Category of an error, reported in json output along with its exit code
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Category {
+    Config,
+    Io,
+    Database,
+    Keystore,
+    Address,
+    Rpc,
+    Transaction,
+    Decode,
+    Runtime,
+}
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Config => "config",
+            Category::Io => "io",
+            Category::Database => "database",
+            Category::Keystore => "keystore",
+            Category::Address => "address",
+            Category::Rpc => "rpc",
+            Category::Transaction => "transaction",
+            Category::Decode => "decode",
+            Category::Runtime => "runtime",
+        }
+    }
+
+    /// Exit code of the category, runtime errors start at 10 and each has its own code
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Category::Config => 2,
+            Category::Io => 3,
+            Category::Database => 4,
+            Category::Keystore => 5,
+            Category::Address => 6,
+            Category::Rpc => 7,
+            Category::Transaction => 8,
+            Category::Decode => 9,
+            Category::Runtime => 10,
+        }
+    }
 }
 
 impl Error {
-    pub fn new(msg: &str) -> Error {
-        Error {
-            details: msg.to_string(),
+    pub fn category(&self) -> Category {
+        match self {
+            Error::Config(_)
+            | Error::ConfigFile(_)
+            | Error::ConfigWrite(_)
+            | Error::Date(_)
+            | Error::Csv(_) => Category::Config,
+            Error::Io(_) => Category::Io,
+            Error::Database(_) | Error::Sqlite(_) => Category::Database,
+            Error::Keystore(_)
+            | Error::KeystoreBackend(_)
+            | Error::Secret(_)
+            | Error::Kdf(_)
+            | Error::Cipher(_) => Category::Keystore,
+            Error::Address { .. } => Category::Address,
+            Error::Node(_) | Error::Rpc { .. } => Category::Rpc,
+            Error::Transaction(_) => Category::Transaction,
+            Error::Decode(_) | Error::Codec(_) | Error::Hex(_) | Error::Blob(_) => Category::Decode,
+            Error::Runtime(_) => Category::Runtime,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Runtime(err) => err.exit_code(),
+            _ => self.category().exit_code(),
+        }
+    }
+
+    pub fn runtime_error(&self) -> Option<&RuntimeError> {
+        match self {
+            Error::Runtime(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Whether an rpc call failed because the connection was lost or timed out, rather than
+    /// being answered with an error, the node may then have handled the call or not
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            Error::Rpc {
+                source: jsonrpsee_core::Error::Transport(_)
+                    | jsonrpsee_core::Error::RestartNeeded(_)
+                    | jsonrpsee_core::Error::RequestTimeout,
+                ..
+            }
+        )
+    }
+
+    /// The data attached by the node to a failed rpc call, for rejected transactions it holds the validity error
+    pub fn rpc_error_data(&self) -> Option<&str> {
+        match self {
+            Error::Rpc { data, .. } => data.as_deref(),
+            _ => None,
         }
     }
 }
 
 impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        &self.details
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ConfigFile(err) => Some(err),
+            Error::ConfigWrite(err) => Some(err),
+            Error::Date(err) => Some(err),
+            Error::Csv(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Sqlite(err) => Some(err),
+            Error::KeystoreBackend(err) => Some(err),
+            Error::Secret(err) => Some(err),
+            Error::Kdf(err) => Some(err),
+            Error::Cipher(err) => Some(err),
+            Error::Address { source, .. } => Some(source),
+            Error::Rpc { source, .. } => Some(source),
+            Error::Codec(err) => Some(err),
+            Error::Hex(err) => Some(err),
+            Error::Blob(err) => Some(err),
+            Error::Config(_)
+            | Error::Database(_)
+            | Error::Keystore(_)
+            | Error::Node(_)
+            | Error::Transaction(_)
+            | Error::Runtime(_)
+            | Error::Decode(_) => None,
+        }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
+        match self {
+            Error::Config(msg)
+            | Error::Database(msg)
+            | Error::Keystore(msg)
+            | Error::Node(msg)
+            | Error::Transaction(msg)
+            | Error::Decode(msg) => write!(f, "{}", msg),
+            Error::ConfigFile(err) => write!(f, "Invalid config file: {}", err),
+            Error::ConfigWrite(err) => write!(f, "Unable to write the config file: {}", err),
+            Error::Date(err) => write!(f, "Invalid date: {}", err),
+            Error::Csv(err) => write!(f, "Invalid CSV file: {}", err),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Sqlite(err) => write!(f, "Database error: {}", err),
+            Error::KeystoreBackend(err) => write!(f, "Keystore error: {}", err),
+            Error::Secret(err) => write!(f, "Invalid secret: {:?}", err),
+            Error::Kdf(err) => write!(f, "Key derivation failed: {:?}", err),
+            Error::Cipher(err) => write!(f, "Encryption failed: {:?}", err),
+            Error::Address { message, source } => write!(f, "{}, {:?}", message, source),
+            Error::Rpc { source, .. } => write!(f, "RPC error: {}", source),
+            Error::Runtime(err) => write!(f, "{}", err),
+            Error::Codec(err) => write!(f, "Unable to decode: {}", err),
+            Error::Hex(err) => write!(f, "Invalid hex: {}", err),
+            Error::Blob(err) => write!(f, "Invalid code blob: {:?}", err),
+        }
     }
 }
 
 impl From<jsonrpsee_core::Error> for Error {
     fn from(err: jsonrpsee_core::Error) -> Self {
-        Error::new(&format!("{:#?}", err))
+        let data = match &err {
+            jsonrpsee_core::Error::Call(CallError::Custom(object)) => object.data().map(|data| {
+                serde_json::from_str::<String>(data.get()).unwrap_or(data.get().to_owned())
+            }),
+            _ => None,
+        };
+        Error::Rpc { source: err, data }
     }
 }
 
 impl From<parity_scale_codec::Error> for Error {
     fn from(err: parity_scale_codec::Error) -> Self {
-        Error::new(&format!("{:#?}", err))
-    }
-}
-
-impl From<dotenv::Error> for Error {
-    fn from(err: dotenv::Error) -> Self {
-        Error::new(&format!("{:#?}", err))
-    }
-}
-
-impl From<std::env::VarError> for Error {
-    fn from(err: std::env::VarError) -> Self {
-        Error::new(&format!("{:#?}", err))
+        Error::Codec(err)
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
-        Error::new(&format!("{:#?}", err))
+        Error::Io(err)
     }
 }
 
 impl From<sqlite::Error> for Error {
     fn from(err: sqlite::Error) -> Self {
-        Error::new(&format!("{:#?}", err))
+        Error::Sqlite(err)
     }
 }
 
 impl From<sp_keystore::Error> for Error {
     fn from(err: sp_keystore::Error) -> Self {
-        Error::new(&format!("{:#?}", err))
+        Error::KeystoreBackend(err)
+    }
+}
+
+impl From<sp_core::crypto::SecretStringError> for Error {
+    fn from(err: sp_core::crypto::SecretStringError) -> Self {
+        Error::Secret(err)
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(err: hex::FromHexError) -> Self {
+        Error::Hex(err)
+    }
+}
+
+impl From<argon2::Error> for Error {
+    fn from(err: argon2::Error) -> Self {
+        Error::Kdf(err)
     }
 }
 
-impl From<sp_core::crypto::PublicError> for Error {
-    fn from(err: sp_core::crypto::PublicError) -> Self {
-        Error::new(&format!("{:#?}", err))
+impl From<chacha20poly1305::Error> for Error {
+    fn from(err: chacha20poly1305::Error) -> Self {
+        Error::Cipher(err)
+    }
+}
+
+impl From<sp_maybe_compressed_blob::Error> for Error {
+    fn from(err: sp_maybe_compressed_blob::Error) -> Self {
+        Error::Blob(err)
+    }
+}
+
+impl From<chrono::ParseError> for Error {
+    fn from(err: chrono::ParseError) -> Self {
+        Error::Date(err)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Error::ConfigFile(err)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Self {
+        Error::ConfigWrite(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_store::KeyStore;
+    use parity_scale_codec::Decode;
+    use std::error::Error as _;
+
+    #[test]
+    fn keeps_wrapped_errors_as_source() {
+        let address = KeyStore::get_pub_key("not an address").unwrap_err();
+        assert_eq!(address.category(), Category::Address);
+        assert!(address.source().is_some());
+        let codec = Error::from(u32::decode(&mut &[0u8][..]).unwrap_err());
+        assert!(codec.source().is_some());
+        let hex = Error::from(<Vec<u8> as hex::FromHex>::from_hex("0").unwrap_err());
+        assert!(hex.source().is_some());
+        assert!(Error::Config("missing".to_owned()).source().is_none());
+    }
+
+    #[test]
+    fn tells_lost_connections_from_answered_calls() {
+        assert!(Error::from(jsonrpsee_core::Error::RequestTimeout).is_connection_error());
+        assert!(
+            Error::from(jsonrpsee_core::Error::RestartNeeded("closed".to_owned()))
+                .is_connection_error()
+        );
+        assert!(
+            !Error::from(jsonrpsee_core::Error::Custom("invalid".to_owned())).is_connection_error()
+        );
+        assert!(!Error::Node("Unable to connect".to_owned()).is_connection_error());
     }
 }
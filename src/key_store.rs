@@ -1,33 +1,303 @@
+use crate::db::DB;
 use crate::error::Error;
-use sp_application_crypto::CryptoTypePublicPair;
-use sp_core::sr25519::Public;
-use sp_core::testing::SR25519;
-use sp_keystore::{testing, SyncCryptoStore};
+use crate::vault::Vault;
+use sp_core::crypto::{AccountId32, Pair as PairT, Ss58Codec};
+use sp_core::{ecdsa, ed25519, sr25519};
+use sp_runtime::traits::IdentifyAccount;
+use sp_runtime::{MultiSignature, MultiSigner};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Signature schemes supported for wallet accounts
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Scheme {
+    Sr25519,
+    Ed25519,
+    Ecdsa,
+}
+
+impl Scheme {
+    pub const NAMES: [&'static str; 3] = ["sr25519", "ed25519", "ecdsa"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Sr25519 => "sr25519",
+            Scheme::Ed25519 => "ed25519",
+            Scheme::Ecdsa => "ecdsa",
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sr25519" => Ok(Scheme::Sr25519),
+            "ed25519" => Ok(Scheme::Ed25519),
+            "ecdsa" => Ok(Scheme::Ecdsa),
+            _ => Err(Error::Config(format!("Unknown signature scheme: {}", s))),
+        }
+    }
+}
+
+/// Key pair of any of the supported schemes
+pub enum KeyPair {
+    Sr25519(sr25519::Pair),
+    Ed25519(ed25519::Pair),
+    Ecdsa(ecdsa::Pair),
+}
+
+impl KeyPair {
+    pub fn from_seed(scheme: Scheme, seed: &str) -> Result<KeyPair, Error> {
+        let pair = match scheme {
+            Scheme::Sr25519 => KeyPair::Sr25519(sr25519::Pair::from_string(seed, None)?),
+            Scheme::Ed25519 => KeyPair::Ed25519(ed25519::Pair::from_string(seed, None)?),
+            Scheme::Ecdsa => KeyPair::Ecdsa(ecdsa::Pair::from_string(seed, None)?),
+        };
+        Ok(pair)
+    }
+
+    pub fn signer(&self) -> MultiSigner {
+        match self {
+            KeyPair::Sr25519(pair) => pair.public().into(),
+            KeyPair::Ed25519(pair) => pair.public().into(),
+            KeyPair::Ecdsa(pair) => pair.public().into(),
+        }
+    }
+
+    /// The account id of the key, the public key for sr25519 and ed25519, the hash of the public key for ecdsa
+    pub fn account(&self) -> AccountId32 {
+        self.signer().into_account()
+    }
+
+    pub fn sign(&self, payload: &[u8]) -> MultiSignature {
+        match self {
+            KeyPair::Sr25519(pair) => pair.sign(payload).into(),
+            KeyPair::Ed25519(pair) => pair.sign(payload).into(),
+            KeyPair::Ecdsa(pair) => pair.sign(payload).into(),
+        }
+    }
+}
+
+/// File backed keystore, every key is stored in its own file named after its scheme and hex encoded account id,
+/// the file holds the mnemonic encrypted with the wallet vault
 pub struct KeyStore {
-    keystore: testing::KeyStore,
+    path: PathBuf,
+    keys: Vec<(AccountId32, Scheme)>,
+    pairs: HashMap<AccountId32, KeyPair>,
+    vault: Option<Vault>,
 }
 
 impl KeyStore {
-    pub fn new() -> KeyStore {
-        KeyStore {
-            keystore: testing::KeyStore::new(),
+    /// Opens the keystore directory creating it if necessary, and loads the keys it holds
+    pub fn open(path: &Path) -> Result<KeyStore, Error> {
+        if !path.exists() {
+            fs::create_dir_all(path)?;
+            Self::set_permissions(path, 0o700)?;
+        }
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let file_name = entry?.file_name();
+            if let Some(key) = file_name.to_str().and_then(Self::key_from_file_name) {
+                keys.push(key);
+            }
         }
+        Ok(KeyStore {
+            path: path.to_owned(),
+            keys,
+            pairs: HashMap::new(),
+            vault: None,
+        })
     }
 
-    pub fn add(&self, mnemonic: &str) -> Result<Public, Error> {
-        let key = SyncCryptoStore::sr25519_generate_new(&self.keystore, SR25519, Some(&mnemonic))?;
-        Ok(key)
+    /// Decrypts the key material of all the stored keys so that they can be used for signing
+    pub fn unlock(&mut self, vault: Vault) -> Result<(), Error> {
+        for (account, scheme) in &self.keys {
+            let encrypted = fs::read_to_string(self.find_key_file_path(account, *scheme))?;
+            let pair = KeyPair::from_seed(*scheme, &vault.decrypt(encrypted.trim())?)?;
+            if pair.account() != *account {
+                return Err(Error::Keystore(format!(
+                    "Keystore file for account: {} holds a different key",
+                    account.to_ss58check()
+                )));
+            }
+            self.pairs.insert(account.clone(), pair);
+        }
+        self.vault = Some(vault);
+        Ok(())
     }
 
-    pub fn sign(&self, key: &Public, payload: &[u8]) -> Result<Vec<u8>, Error> {
-        let keypair = CryptoTypePublicPair(sp_core::sr25519::CRYPTO_ID, key.0.into());
-        let signature = SyncCryptoStore::sign_with(&self.keystore, SR25519, &keypair, &payload)?
-            .ok_or(Error::new("Failed to sign payload"))?;
-        Ok(signature)
+    /// Unlocks the keystore, importing any account of the db that was stored before the keystore existed
+    pub fn unlock_wallet(&mut self, db: &DB, vault: Vault) -> Result<(), Error> {
+        self.unlock(vault.clone())?;
+        for account in db.get_accounts()? {
+            if account.watch_only || self.contains(&Self::get_pub_key(&account.address)?) {
+                continue;
+            }
+            let account = db.get_account(&vault, &account.address)?;
+            if let (Some(mnemonic), Some(scheme)) = (account.mnemonic, account.scheme) {
+                self.add(&mnemonic, scheme.parse()?)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn get_pub_key(address: &str) -> Result<Public, Error> {
-      let key :Public = address.parse()?;
-      Ok(key)
+    /// Adds a key to the keystore, persisting it encrypted, requires the keystore to be unlocked
+    pub fn add(&mut self, mnemonic: &str, scheme: Scheme) -> Result<AccountId32, Error> {
+        let vault = self
+            .vault
+            .as_ref()
+            .ok_or(Error::Keystore("Keystore is locked".to_owned()))?;
+        let pair = KeyPair::from_seed(scheme, mnemonic)?;
+        let account = pair.account();
+        if !self.contains(&account) {
+            let encrypted = vault.encrypt(mnemonic)?;
+            let mut file = Self::create_key_file(&self.key_file_path(&account, scheme))?;
+            file.write_all(encrypted.as_bytes())?;
+            file.sync_all()?;
+            self.keys.push((account.clone(), scheme));
+        }
+        self.pairs.insert(account.clone(), pair);
+        Ok(account)
+    }
+
+    /// Adds a key that is only kept in memory for the duration of the run
+    pub fn add_ephemeral(&mut self, seed: &str, scheme: Scheme) -> Result<AccountId32, Error> {
+        let pair = KeyPair::from_seed(scheme, seed)?;
+        let account = pair.account();
+        self.pairs.insert(account.clone(), pair);
+        Ok(account)
+    }
+
+    pub fn contains(&self, account: &AccountId32) -> bool {
+        self.keys.iter().any(|(key, _)| key == account)
+    }
+
+    /// Signs the payload with the key of the account, returns the signer along with the signature
+    pub fn sign(
+        &self,
+        account: &AccountId32,
+        payload: &[u8],
+    ) -> Result<(MultiSigner, MultiSignature), Error> {
+        let pair = self.pairs.get(account).ok_or(Error::Keystore(
+            "Key not available for signing, make sure the account has been added and the keystore is unlocked".to_owned(),
+        ))?;
+        Ok((pair.signer(), pair.sign(payload)))
+    }
+
+    /// Parses an ss58 address into an account id, independently of the scheme of the key behind it
+    pub fn get_pub_key(address: &str) -> Result<AccountId32, Error> {
+        AccountId32::from_ss58check(address).map_err(|source| Error::Address {
+            message: format!("Invalid address: {}", address),
+            source,
+        })
+    }
+
+    pub fn account_from_seed(seed: &str, scheme: Scheme) -> Result<AccountId32, Error> {
+        Ok(KeyPair::from_seed(scheme, seed)?.account())
+    }
+
+    fn key_file_path(&self, account: &AccountId32, scheme: Scheme) -> PathBuf {
+        let account: &[u8] = account.as_ref();
+        self.path
+            .join(format!("{}_{}", scheme, hex::encode(account)))
+    }
+
+    // Keys stored before multiple schemes were supported are sr25519 keys named by account only
+    fn find_key_file_path(&self, account: &AccountId32, scheme: Scheme) -> PathBuf {
+        let path = self.key_file_path(account, scheme);
+        if path.exists() {
+            return path;
+        }
+        let account: &[u8] = account.as_ref();
+        self.path.join(hex::encode(account))
+    }
+
+    fn key_from_file_name(file_name: &str) -> Option<(AccountId32, Scheme)> {
+        let (scheme, account) = match file_name.split_once('_') {
+            Some((scheme, account)) => (scheme.parse().ok()?, account),
+            None => (Scheme::Sr25519, file_name),
+        };
+        let bytes: [u8; 32] = hex::decode(account).ok()?.try_into().ok()?;
+        Some((AccountId32::new(bytes), scheme))
+    }
+
+    #[cfg(unix)]
+    fn create_key_file(path: &Path) -> Result<fs::File, Error> {
+        use std::os::unix::fs::OpenOptionsExt;
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        Ok(file)
+    }
+
+    #[cfg(not(unix))]
+    fn create_key_file(path: &Path) -> Result<fs::File, Error> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Ok(file)
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(path: &Path, mode: u32) -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(_path: &Path, _mode: u32) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::vault::KdfParams;
+    use sp_runtime::traits::Verify;
+    use tempfile::TempDir;
+
+    #[test]
+    fn keys_persist_across_keystore_instances() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("keystore");
+        let vault = Vault::new("passphrase", &KdfParams::generate()).unwrap();
+        let mnemonic = "kick argue bright nose service uphold slogan real erupt vote suffer drama";
+
+        let mut keystore = KeyStore::open(&path).unwrap();
+        keystore.unlock(vault.clone()).unwrap();
+        let accounts: Vec<AccountId32> = Scheme::NAMES
+            .iter()
+            .map(|scheme| keystore.add(mnemonic, scheme.parse().unwrap()).unwrap())
+            .collect();
+
+        let mut keystore = KeyStore::open(&path).unwrap();
+        for account in &accounts {
+            assert!(keystore.contains(account));
+            assert!(keystore.sign(account, b"payload").is_err());
+        }
+        keystore.unlock(vault).unwrap();
+        for account in accounts {
+            let (signer, signature) = keystore.sign(&account, b"payload").unwrap();
+            assert_eq!(signer.into_account(), account);
+            assert!(signature.verify(&b"payload"[..], &account));
+        }
     }
 }
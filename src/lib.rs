@@ -0,0 +1,68 @@
+//! Client of the chain whose runtime mints tokens, transfers them and sets the min fee.
+//!
+//! [`API`] is the async client: it reads balances, the total supply and the min fee, and submits
+//! extrinsics signed offline. Given a wallet, it also signs calls with the keys of a [`KeyStore`],
+//! submits them and follows them until they are in a block, recording them in the wallet database
+//! [`DB`]. Its methods return typed results, rendering them is up to the caller. Progress, like the
+//! status changes of a submitted transaction or a lost connection, is reported through the `log`
+//! crate. Settings like the admin account and the token denomination are given with [`ApiOptions`],
+//! the client doesn't read the environment, the cli resolves them with [`config::Settings`].
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use wallet_cli::{ApiOptions, KeyStore, RpcOptions, WaitFor, API, DB, RPC};
+//!
+//! # async fn example() -> Result<(), wallet_cli::Error> {
+//! let rpc = RPC::new(&["ws://127.0.0.1:9944".to_owned()], RpcOptions::default())?;
+//! let reader = API::new(rpc, ApiOptions::default());
+//! println!("min fee: {}", reader.get_min_fee(None).await?);
+//!
+//! let rpc = RPC::new(&["ws://127.0.0.1:9944".to_owned()], RpcOptions::default())?;
+//! let db = DB::new("wallet.sql")?;
+//! let mut keystore = KeyStore::open(Path::new("keystore"))?;
+//! keystore.unlock_wallet(&db, db.unlock_vault("passphrase")?)?;
+//! let api = API::new(rpc, ApiOptions::default()).with_wallet(keystore, db);
+//!
+//! let sender = api.db()?.get_default_account()?;
+//! println!("balance: {}", api.get_balance(&sender, None).await?);
+//! let recipient = "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty";
+//! let fee = api.get_min_fee(None).await?;
+//! let result = api
+//!     .transfer(&KeyStore::get_pub_key(&sender)?, recipient, 10, fee, WaitFor::InBlock)
+//!     .await?;
+//! println!("tx: {} in block: {:?}", result.hash, result.status.block_hash());
+//! # Ok(())
+//! # }
+//! ```
+
+/// The async client of the node
+pub mod api;
+/// Transfers to many recipients read from a csv file
+pub mod batch;
+/// Named network profiles stored in the config file, and the settings resolved from them and the other sources
+pub mod config;
+/// The wallet database: accounts, labels, the encrypted vault and the transactions ledger
+pub mod db;
+/// Errors of the wallet, grouped in categories with their own exit codes
+pub mod error;
+/// Keys of the wallet accounts, stored encrypted on disk
+pub mod key_store;
+/// Json rpc connection to the node, over ws or http, with failover between endpoints
+pub mod rpc;
+/// Connections over TLS trusting a CA bundle
+mod tls;
+/// Files holding unsigned payloads and signed extrinsics for offline signing
+pub mod tx_file;
+/// SCALE types of the runtime
+pub mod types;
+/// Conversion between token amounts and their decimal representation
+pub mod units;
+/// Passphrase based encryption of the wallet secrets
+pub mod vault;
+
+pub use api::{ApiOptions, StorageItem, TxResult, WaitFor, API};
+pub use db::DB;
+pub use error::Error;
+pub use key_store::{KeyStore, Scheme};
+pub use rpc::{RpcOptions, RPC};
+pub use units::Denomination;
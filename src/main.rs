@@ -1,28 +1,39 @@
-use dotenv::dotenv;
+use chrono::NaiveDate;
+use parity_scale_codec::Encode;
 use sp_application_crypto::Ss58Codec;
-use std::path::PathBuf;
-use std::{ffi::OsString, iter::Product};
+use sp_core::crypto::AccountId32;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use clap::{arg, value_parser, Arg, ArgAction, ArgMatches, Command};
 
-mod api;
-mod db;
-mod error;
-mod key_store;
-mod rpc;
-mod types;
+mod output;
 
-use api::*;
-use db::*;
-use key_store::*;
-use rpc::*;
+use output::{Output, OutputFormat};
+use serde_json::json;
+use wallet_cli::api::*;
+use wallet_cli::config::{self, Config, NetworkProfile, Settings, Vars};
+use wallet_cli::db::*;
+use wallet_cli::key_store::*;
+use wallet_cli::rpc::*;
+use wallet_cli::units::Denomination;
+use wallet_cli::vault::Vault;
+use wallet_cli::{batch, error, tx_file, types};
 
 #[tokio::main]
 async fn main() {
-    if let Err(error) = run().await {
-        println!("Error: {}", error);
+    let matches = cli().get_matches();
+    let format: OutputFormat = matches
+        .get_one::<String>("output")
+        .expect("defaulted in clap")
+        .parse()
+        .expect("restricted by clap");
+    let output = Output::new(format);
+    output::init_progress();
+    if let Err(error) = run(&matches, &output).await {
+        output.error(&error);
+        std::process::exit(error.exit_code());
     }
-    // Continued program logic goes here...
 }
 
 fn cli() -> Command {
@@ -30,7 +41,95 @@ fn cli() -> Command {
         .about("A wallet cli, to view balances, transfer tokens and administer token ")
         .subcommand_required(true)
         .arg_required_else_help(true)
-        .allow_external_subcommands(true)
+        .arg(
+            arg!(--network <name> "Network profile to use, by default the one selected with network use")
+                .global(true),
+        )
+        .arg(
+            arg!(--endpoint <url> "Node to connect to, as a ws, wss, http or https url, or a comma separated list of nodes to fail over to in order, overrides ADDR and the network profile")
+                .global(true),
+        )
+        .arg(
+            arg!(--database <file> "Wallet database file, overrides DATABASE_FILE and the network profile")
+                .global(true),
+        )
+        .arg(
+            arg!(--header <header> "Header sent to the node, as name: value, can be repeated")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .arg(
+            arg!(--"ca-bundle" <file> "PEM file with the certificates to trust for wss and https endpoints, besides the system ones")
+                .value_parser(value_parser!(PathBuf))
+                .global(true),
+        )
+        .arg(
+            arg!(--"connect-timeout" <seconds> "Timeout to connect to ws endpoints, requests to http endpoints are bounded by --request-timeout")
+                .value_parser(value_parser!(u64))
+                .default_value("10")
+                .global(true),
+        )
+        .arg(
+            arg!(--"request-timeout" <seconds> "Timeout of every request to the node")
+                .value_parser(value_parser!(u64))
+                .default_value("60")
+                .global(true),
+        )
+        .arg(
+            arg!(--at <block> "Block hash or number to read the state at, for get-balance, get-total-supply, get-min-fee and portfolio")
+                .global(true),
+        )
+        .arg(
+            arg!(--output <format> "Format of the results printed on stdout, progress messages go to stderr")
+                .value_parser(OutputFormat::NAMES)
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            arg!(--raw "Amounts are given and printed in base units instead of tokens")
+                .global(true),
+        )
+        .arg(
+            arg!(--verify "Checks every value read from the node against a storage proof and the block header. Reading from untrusted nodes needs --at with a block hash from a trusted source, without it the node picks the block, and a block number is resolved by the node too")
+                .global(true),
+        )
+        .after_help(
+            "Exit codes:
+  2  invalid arguments, settings or input files    6  invalid address
+  3  local file can't be read or written           7  node unreachable or rpc call failed
+  4  wallet database error or missing entry        8  transaction didn't reach the expected outcome
+  5  keystore, passphrase or signing error         9  data from the node or a file can't be decoded
+Transactions rejected by the runtime:
+  10  NoSignature       11  NoAdminKey     12  InvalidSignature
+  13  SupplyOverflow    14  BalanceTooLow  15  FeeTooLow",
+        )
+        .subcommand(
+            Command::new("network")
+                .about("Manages the network profiles, each with its own endpoint, wallet database, token settings and admin account")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Adds or replaces a network profile, the endpoint is given with --endpoint and the database with --database")
+                        .arg(Arg::new("name").required(true).help("Name of the network"))
+                        .arg(arg!(--decimals <decimals> "Token decimals, read from the chain if not set").value_parser(value_parser!(u8)))
+                        .arg(arg!(--symbol <symbol> "Token symbol, read from the chain if not set"))
+                        .arg(arg!(--admin <address> "Address of the admin account, its key has to be in the wallet"))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(Command::new("list").about("Lists the network profiles"))
+                .subcommand(
+                    Command::new("use")
+                        .about("Selects the network used when --network is not given")
+                        .arg(Arg::new("name").required(true).help("Name of the network"))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Removes a network profile, its wallet database is kept")
+                        .arg(Arg::new("name").required(true).help("Name of the network"))
+                        .arg_required_else_help(true),
+                ),
+        )
         .subcommand(
             Command::new("add-account")
                 .about("Adds an account to the wallet")
@@ -40,8 +139,28 @@ fn cli() -> Command {
                         .required(true)
                         .help("mnemonic of the account"),
                 )
+                .arg(
+                    arg!(--scheme <scheme> "Signature scheme of the account")
+                        .value_parser(Scheme::NAMES)
+                        .default_value("sr25519"),
+                )
                 .arg_required_else_help(true),
         )
+        .subcommand(
+            Command::new("watch-account")
+                .about("Adds a watch-only account to the wallet, it can be used to view balances but not to sign transactions")
+                .arg(
+                    Arg::new("address")
+                        .action(ArgAction::Set)
+                        .required(true)
+                        .help("The address of the account to watch"),
+                )
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("encrypt-wallet")
+                .about("Encrypts the mnemonics stored in the wallet with a passphrase, only needed for wallets created before encryption was supported")
+        )
         .subcommand(
             Command::new("get-accounts")
                 .about("Views accounts stored in wallet")
@@ -57,9 +176,45 @@ fn cli() -> Command {
                     Arg::new("account")
                         .action(ArgAction::Set)
                         .required(false)
-                        .help("The address of the account to view the balance for"),
+                        .help("The address or label of the account to view the balance for"),
                 ),
         )
+        .subcommand(
+            Command::new("watch-balance")
+                .about("Prints every change of the balances of the specified accounts as it happens, if no account is specified the default account is watched")
+                .arg(
+                    Arg::new("account")
+                        .action(ArgAction::Append)
+                        .required(false)
+                        .help("The addresses or labels of the accounts to watch"),
+                )
+                .arg(arg!(--supply "Also watch the total supply"))
+                .arg(arg!(--"min-fee" "Also watch the min fee")),
+        )
+        .subcommand(
+            Command::new("index")
+                .about("Scans the finalized blocks for transactions touching wallet accounts and adds them to the history, resuming from the last block scanned"),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Lists the transactions submitted from this wallet and the ones found by index, oldest first")
+                .arg(
+                    Arg::new("account")
+                        .action(ArgAction::Set)
+                        .required(false)
+                        .help("The address or label of an account, only transactions it signed or received are listed"),
+                )
+                .arg(
+                    arg!(--call <call> "Only list transactions of this type")
+                        .value_parser(["mint", "transfer", "set-min-fee", "upgrade"]),
+                )
+                .arg(arg!(--since <date> "Only list transactions submitted on or after this date, as YYYY-MM-DD"))
+                .arg(arg!(--until <date> "Only list transactions submitted on or before this date, as YYYY-MM-DD")),
+        )
+        .subcommand(
+            Command::new("portfolio")
+                .about("Views the balances of all the accounts stored in the wallet, read from the same block"),
+        )
         .subcommand(
             Command::new("get-default-account")
                 .about("View current default account")
@@ -69,256 +224,1278 @@ fn cli() -> Command {
         )
         .subcommand(Command::new("get-total-supply").about("Gets the current total supply"))
         .subcommand(
-            Command::new("mint")
-                .about("Mints tokens for the specified account, has to be executed as admin")
+            mint_command()
+                .arg(get_wait_for_arg())
+                .arg(get_dry_run_arg()),
+        )
+        .subcommand(
+            transfer_command()
+                .arg(get_wait_for_arg())
+                .arg(get_dry_run_arg()),
+        )
+        .subcommand(
+            Command::new("transfer-batch")
+                .about("Transfers tokens from the default account to every recipient of a CSV file with recipient,amount and an optional fee column. The outcome of every row is written to a results file, running the command again resumes the batch skipping the rows that succeeded")
                 .arg(
-                    Arg::new("account")
-                        .action(ArgAction::Set)
+                    Arg::new("file")
+                        .value_parser(value_parser!(PathBuf))
                         .required(true)
-                        .help("The address of the account to mint tokens for"),
+                        .help("The CSV file with the transfers, amounts in tokens or in base units with --raw"),
                 )
                 .arg(
-                    Arg::new("amount")
-                        .value_parser(value_parser!(u128))
-                        .action(ArgAction::Set)
+                    arg!(--results <file> "File to write the results to, by default the input file with a results.csv extension")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(get_tx_fee_arg())
+                .arg(get_wait_for_arg())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            set_min_fee_command()
+                .arg(get_wait_for_arg())
+                .arg(get_dry_run_arg()),
+        )
+        .subcommand(
+            Command::new("upgrade-runtime")
+                .about("Upgrades the runtime to the specified wasm code, has to be executed as admin")
+                .arg(
+                    Arg::new("code")
+                        .value_parser(value_parser!(PathBuf))
                         .required(true)
-                        .help("The amount to mint"),
+                        .help("The runtime wasm file, either plain or compressed"),
                 )
                 .arg(get_tx_fee_arg())
+                .arg(get_wait_for_arg())
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("set-default-account")
+                .about("Sets the default account to operate with, the account must have been added beforehand")
+                .arg(
+                    Arg::new("account")
+                        .action(ArgAction::Set)
+                        .required(true)
+                        .help("The address or label of the account to set as default"),
+                )
                 .arg_required_else_help(true),
         )
+        .subcommand(tx_command())
         .subcommand(
-            Command::new("transfer")
-                .about("Transfers tokens to the specified account")
+            Command::new("set-label")
+                .about("Sets a label for an account stored in the wallet, the label can be used anywhere an address is accepted")
                 .arg(
                     Arg::new("account")
                         .action(ArgAction::Set)
                         .required(true)
-                        .help("The address of the account to transfer tokens to"),
+                        .help("The address or current label of the account to label"),
                 )
                 .arg(
-                    Arg::new("amount")
-                        .value_parser(value_parser!(u128))
+                    Arg::new("label")
                         .action(ArgAction::Set)
                         .required(true)
-                        .help("The amount to transfer"),
+                        .help("The label to set"),
                 )
-                .arg(get_tx_fee_arg())
                 .arg_required_else_help(true),
         )
         .subcommand(
-            Command::new("set-min-fee")
-                .about("Sets the min fee for transactions, has to be executed as admin")
+            Command::new("clear-label")
+                .about("Removes the label of an account stored in the wallet")
                 .arg(
-                    Arg::new("min-fee")
-                        .value_parser(value_parser!(u32))
+                    Arg::new("account")
                         .action(ArgAction::Set)
                         .required(true)
-                        .help("The amount to set the min fee to"),
+                        .help("The address or label of the account"),
                 )
-                .arg(get_tx_fee_arg())
                 .arg_required_else_help(true),
         )
+}
+
+fn mint_command() -> Command {
+    Command::new("mint")
+        .about("Mints tokens for the specified account, has to be executed as admin")
+        .arg(
+            Arg::new("account")
+                .action(ArgAction::Set)
+                .required(true)
+                .help("The address or label of the account to mint tokens for"),
+        )
+        .arg(
+            Arg::new("amount")
+                .action(ArgAction::Set)
+                .required(true)
+                .help("The amount to mint, in tokens like 12.5 or 12.5 UNIT"),
+        )
+        .arg(get_tx_fee_arg())
+        .arg_required_else_help(true)
+}
+
+fn transfer_command() -> Command {
+    Command::new("transfer")
+        .about("Transfers tokens to the specified account")
+        .arg(
+            Arg::new("account")
+                .action(ArgAction::Set)
+                .required(true)
+                .help("The address or label of the account to transfer tokens to"),
+        )
+        .arg(
+            Arg::new("amount")
+                .action(ArgAction::Set)
+                .required(true)
+                .help("The amount to transfer, in tokens like 12.5 or 12.5 UNIT"),
+        )
+        .arg(get_tx_fee_arg())
+        .arg_required_else_help(true)
+}
+
+fn set_min_fee_command() -> Command {
+    Command::new("set-min-fee")
+        .about("Sets the min fee for transactions, has to be executed as admin")
+        .arg(
+            Arg::new("min-fee")
+                .value_parser(value_parser!(u32))
+                .action(ArgAction::Set)
+                .required(true)
+                .help("The amount to set the min fee to, in base units of the token"),
+        )
+        .arg(get_tx_fee_arg())
+        .arg_required_else_help(true)
+}
+
+fn tx_command() -> Command {
+    let out_arg = arg!(--out <file> "File to write the unsigned payload to")
+        .value_parser(value_parser!(PathBuf))
+        .required(true);
+    Command::new("tx")
+        .about("Offline signing workflow: build an unsigned payload, sign it on an offline machine and broadcast it")
+        .subcommand_required(true)
         .subcommand(
-            Command::new("set-default-account")
-                .about("Sets the default account to operate with, the account must have been added beforehand")
+            Command::new("build")
+                .about("Builds an unsigned payload and writes it to a file")
+                .subcommand_required(true)
+                .subcommand(
+                    transfer_command()
+                        .arg(arg!(--from <account> "Address or label of the sender, if not specified the default account is used"))
+                        .arg(out_arg.clone()),
+                )
+                .subcommand(mint_command().arg(out_arg.clone()))
+                .subcommand(set_min_fee_command().arg(out_arg)),
+        )
+        .subcommand(
+            Command::new("sign")
+                .about("Signs an unsigned payload file, does not require a connection to the node")
                 .arg(
-                    Arg::new("account")
-                        .action(ArgAction::Set)
+                    Arg::new("input")
+                        .value_parser(value_parser!(PathBuf))
                         .required(true)
-                        .help("The address of the account to set as default"),
+                        .help("The unsigned payload file"),
                 )
+                .arg(
+                    Arg::new("output")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("File to write the signed extrinsic to"),
+                )
+                .arg(arg!(--account <account> "Address or label of the signer, if not specified the sender is used for transfers and the admin for admin calls"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("broadcast")
+                .about("Submits a signed extrinsic file")
+                .arg(
+                    Arg::new("input")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("The signed extrinsic file"),
+                )
+                .arg(get_wait_for_arg())
                 .arg_required_else_help(true),
         )
 }
 
-async fn run() -> Result<(), error::Error> {
-    dotenv()?;
-    let matches = cli().get_matches();
-    let addr = std::env::var("ADDR")?;
-    let db_file = std::env::var("DATABASE_FILE")?;
-    let db = DB::new(&db_file)?;
-    let api = API::new(RPC::new(&addr).await?, KeyStore::new());
+async fn run(matches: &ArgMatches, output: &Output) -> Result<(), error::Error> {
+    let config_path = std::env::var("WALLET_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Config::default_path());
+    let mut config = Config::load(&config_path)?;
+    if let Some(("network", sub_matches)) = matches.subcommand() {
+        return network(&mut config, &config_path, sub_matches, output);
+    }
+    let settings = Settings::load(
+        get_flag_settings(matches),
+        config.profile(matches.get_one::<String>("network").map(String::as_str))?,
+    );
+    let options = settings.api_options(matches.get_flag("verify"))?;
+    let mut keystore = KeyStore::open(&settings.keystore_path()?)?;
+    if let Some(admin_seed) = &options.admin_seed {
+        keystore.add_ephemeral(admin_seed, Scheme::Sr25519)?;
+    }
+    // the endpoint is only needed once a command talks to the node, so that offline commands run without one
+    let rpc = match settings.endpoints() {
+        Some(endpoints) => RPC::new(&endpoints, get_rpc_options(matches))?,
+        None => RPC::without_endpoint(get_rpc_options(matches)),
+    };
+    let mut api = API::new(rpc, options).with_wallet(keystore, DB::new(settings.database()?)?);
+    let db = api.db()?;
 
     match matches.subcommand() {
         Some(("add-account", sub_matches)) => {
             let mnemonic = sub_matches
                 .get_one::<String>("mnemonic")
                 .expect("mnemonic is required");
-            let key = api.keystore.add(mnemonic)?;
-            println!("Adding account: {}", key.to_ss58check());
-            db.add_account(&key.to_ss58check(), mnemonic)?;
+            let scheme: Scheme = sub_matches
+                .get_one::<String>("scheme")
+                .expect("defaulted in clap")
+                .parse()?;
+            let vault = unlock_keystore(&mut api, &settings)?;
+            let key = api.add_key(mnemonic, scheme)?;
+            api.db()?
+                .add_account(&vault, &key.to_ss58check(), mnemonic, scheme.as_str())?;
+            output.print(
+                &format!("Added {} account: {}", scheme, key.to_ss58check()),
+                json!({ "account": key.to_ss58check(), "scheme": scheme.as_str() }),
+            );
+        }
+        Some(("watch-account", sub_matches)) => {
+            let address = sub_matches
+                .get_one::<String>("address")
+                .expect("address is required");
+            let address = KeyStore::get_pub_key(address)?.to_ss58check();
+            db.add_watch_account(&address)?;
+            output.print(
+                &format!("Watching account: {}", address),
+                json!({ "account": address, "watch_only": true }),
+            );
+        }
+        Some(("encrypt-wallet", _)) => {
+            let text = if db.has_vault()? {
+                "Wallet is already encrypted"
+            } else {
+                unlock_vault(db, &settings)?;
+                "Wallet encrypted"
+            };
+            output.print(text, json!({ "encrypted": true }));
         }
         Some(("get-min-fee", _)) => {
-            let fee = api.get_min_fee().await?;
-            println!("Current min fee is: {}", fee);
+            let at = get_at(matches, &api).await?;
+            let fee = api.get_min_fee(at.as_deref()).await?;
+            let units = get_units(matches, &api).await?;
+            output.print(
+                &format!("Current min fee is: {}", units.describe(fee as u128)),
+                json!({ "min_fee": fee, "block": at }),
+            );
         }
         Some(("get-total-supply", _)) => {
-            let supply = api.get_supply().await?;
-            println!("Current total supply is: {}", supply);
+            let at = get_at(matches, &api).await?;
+            let supply = api.get_supply(at.as_deref()).await?;
+            let units = get_units(matches, &api).await?;
+            output.print(
+                &format!("Current total supply is: {}", units.format(supply)),
+                json!({ "total_supply": supply.to_string(), "block": at }),
+            );
         }
         Some(("get-accounts", _)) => {
-            println!("Accounts:");
-            for account in db.get_accounts()? {
-                println!("{}", account);
+            let accounts = db.get_accounts()?;
+            let mut lines = vec!["Accounts:".to_owned()];
+            for account in &accounts {
+                let mut line = account.address.clone();
+                if let Some(label) = &account.label {
+                    line = format!("{} [{}]", line, label);
+                }
+                if account.watch_only {
+                    line = format!("{} (watch-only)", line);
+                }
+                lines.push(line);
+            }
+            let accounts: Vec<_> = accounts
+                .iter()
+                .map(|account| {
+                    json!({
+                        "address": account.address,
+                        "label": account.label,
+                        "watch_only": account.watch_only,
+                    })
+                })
+                .collect();
+            output.print(&lines.join("\n"), json!({ "accounts": accounts }));
+        }
+        Some(("watch-balance", sub_matches)) => {
+            let mut targets = Vec::new();
+            for account in sub_matches
+                .get_many::<String>("account")
+                .into_iter()
+                .flatten()
+            {
+                let account = db.resolve_account(account)?;
+                targets.push(StorageItem::Balance(KeyStore::get_pub_key(&account)?));
+            }
+            if sub_matches.get_flag("supply") {
+                targets.push(StorageItem::Supply);
+            }
+            if sub_matches.get_flag("min-fee") {
+                targets.push(StorageItem::MinFee);
             }
+            if targets.is_empty() {
+                targets.push(StorageItem::Balance(KeyStore::get_pub_key(
+                    &db.get_default_account()?,
+                )?));
+            }
+            let units = get_units(matches, &api).await?;
+            watch_balance(&api, &targets, &units, output).await?;
+        }
+        Some(("index", _)) => {
+            let summary = api.index().await?;
+            let text = if summary.from > summary.to {
+                format!("Already up to date with block: {}", summary.to)
+            } else {
+                format!(
+                    "Indexed blocks: {} to {}, found {} wallet transactions",
+                    summary.from, summary.to, summary.found
+                )
+            };
+            output.print(
+                &text,
+                json!({ "from": summary.from, "to": summary.to, "found": summary.found }),
+            );
+        }
+        Some(("history", sub_matches)) => {
+            let filter = TransactionFilter {
+                account: match sub_matches.get_one::<String>("account") {
+                    Some(account) => Some(db.resolve_account(account)?),
+                    None => None,
+                },
+                call: sub_matches.get_one::<String>("call").cloned(),
+                since: get_date_arg(sub_matches, "since")?.map(|date| date.to_string()),
+                // until is inclusive, entries are selected up to the start of the next day
+                until: get_date_arg(sub_matches, "until")?
+                    .and_then(|date| date.succ_opt())
+                    .map(|date| date.to_string()),
+            };
+            let records = db.get_transactions(&filter)?;
+            let units = get_units(matches, &api).await?;
+            let mut lines = vec!["Transactions:".to_owned()];
+            lines.extend(records.iter().map(|record| describe_record(record, &units)));
+            let transactions: Vec<_> = records.iter().map(record_json).collect();
+            output.print(&lines.join("\n"), json!({ "transactions": transactions }));
+        }
+        Some(("portfolio", _)) => {
+            let accounts = db.get_accounts()?;
+            let mut items = vec![StorageItem::Supply];
+            for account in &accounts {
+                items.push(StorageItem::Balance(KeyStore::get_pub_key(
+                    &account.address,
+                )?));
+            }
+            let at = get_at(matches, &api).await?;
+            let (block, values) = api.query_storage(&items, at.as_deref()).await?;
+            let supply = values[0];
+            let units = get_units(matches, &api).await?;
+            let mut lines = vec![
+                format!("Portfolio at block: {}", block),
+                format!(
+                    "{:<48}  {:<16}  {:>24}  {:>8}",
+                    "Address", "Label", "Balance", "Supply"
+                ),
+            ];
+            let mut entries = Vec::new();
+            let mut total: u128 = 0;
+            for (account, balance) in accounts.iter().zip(&values[1..]) {
+                total = total.saturating_add(*balance);
+                lines.push(format!(
+                    "{:<48}  {:<16}  {:>24}  {:>8}",
+                    account.address,
+                    account.label.as_deref().unwrap_or_default(),
+                    units.format(*balance),
+                    supply_share(*balance, supply)
+                ));
+                entries.push(json!({
+                    "address": account.address,
+                    "label": account.label,
+                    "balance": balance.to_string(),
+                    "supply_share": supply_share(*balance, supply),
+                }));
+            }
+            lines.push(format!(
+                "{:<48}  {:<16}  {:>24}  {:>8}",
+                "Total",
+                "",
+                units.format(total),
+                supply_share(total, supply)
+            ));
+            output.print(
+                &lines.join("\n"),
+                json!({
+                    "block": block,
+                    "total_supply": supply.to_string(),
+                    "accounts": entries,
+                    "total": total.to_string(),
+                    "supply_share": supply_share(total, supply),
+                }),
+            );
         }
         Some(("get-default-account", _)) => {
-            println!("Default Account: {}", db.get_default_account()?)
+            let account = db.get_default_account()?;
+            output.print(
+                &format!("Default Account: {}", account),
+                json!({ "default_account": account }),
+            );
         }
         Some(("get-admin-account", _)) => {
-            println!(
-                "Admin Account: {}",
-                api.keystore
-                    .add(&std::env::var("ADMIN_SEED")?)?
-                    .to_ss58check()
+            let account = api.get_admin_key()?.to_ss58check();
+            output.print(
+                &format!("Admin Account: {}", account),
+                json!({ "admin_account": account }),
             );
         }
         Some(("get-balance", sub_matches)) => {
-            let account = get_operating_account(sub_matches, &db)?;
-            println!(
-                "Balance for account: {} is: {}",
-                account,
-                api.get_balance(&account).await?
+            let account = get_operating_account(sub_matches, db)?;
+            let at = get_at(matches, &api).await?;
+            let balance = api.get_balance(&account, at.as_deref()).await?;
+            let units = get_units(matches, &api).await?;
+            output.print(
+                &format!(
+                    "Balance for account: {} is: {}",
+                    account,
+                    units.format(balance)
+                ),
+                json!({ "account": account, "balance": balance.to_string(), "block": at }),
             );
         }
         Some(("mint", sub_matches)) => {
-            let account = sub_matches
-                .get_one::<String>("account")
-                .expect("account is required");
-            let amount = *sub_matches
-                .get_one::<u128>("amount")
-                .expect("amount is required");
-            println!("Minting: {} tokens for account: {} ...", amount, account);
-            let result = api.mint(account, amount).await?;
-            println!("{}", result);
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            let units = get_units(matches, &api).await?;
+            let amount = units.parse(
+                sub_matches
+                    .get_one::<String>("amount")
+                    .expect("amount is required"),
+            )?;
+            unlock_admin(&mut api, &settings)?;
+            if sub_matches.get_flag("dry-run") {
+                let (ext, preflight) = api
+                    .simulate(&api.get_admin_key()?, API::mint_call(&account, amount)?, 0)
+                    .await?;
+                return print_dry_run(&ext, preflight, &units, output);
+            }
+            eprintln!(
+                "Minting: {} for account: {} ...",
+                units.format(amount),
+                account
+            );
+            let result = api
+                .mint(&account, amount, get_wait_for(sub_matches)?)
+                .await?;
+            print_tx_result(&result, output);
         }
         Some(("transfer", sub_matches)) => {
-            let account = sub_matches
-                .get_one::<String>("account")
-                .expect("account is required");
-            let amount = *sub_matches
-                .get_one::<u128>("amount")
-                .expect("amount is required");
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            let units = get_units(matches, &api).await?;
+            let amount = units.parse(
+                sub_matches
+                    .get_one::<String>("amount")
+                    .expect("amount is required"),
+            )?;
             let tx_fee = get_tx_fee(sub_matches, &api).await;
-            let (default_account, mnemonic) = db.get_default_mnemonic()?;
-            println!(
+            let default_account = get_signing_account(db)?;
+            unlock_keystore(&mut api, &settings)?;
+            let from_key = KeyStore::get_pub_key(&default_account)?;
+            if sub_matches.get_flag("dry-run") {
+                let call = API::transfer_call(&from_key, &account, amount)?;
+                let (ext, preflight) = api.simulate(&from_key, call, tx_fee).await?;
+                return print_dry_run(&ext, preflight, &units, output);
+            }
+            eprintln!(
                 "Transfering from {}  to {} amount: {} ...",
-                default_account, account, amount
+                default_account,
+                account,
+                units.format(amount)
             );
-            let result = api.transfer(&mnemonic, account, amount, tx_fee).await?;
-            println!("{}", result);
+            let result = api
+                .transfer(
+                    &from_key,
+                    &account,
+                    amount,
+                    tx_fee,
+                    get_wait_for(sub_matches)?,
+                )
+                .await?;
+            print_tx_result(&result, output);
+        }
+        Some(("transfer-batch", sub_matches)) => {
+            let sender = get_signing_account(db)?;
+            unlock_keystore(&mut api, &settings)?;
+            let units = get_units(matches, &api).await?;
+            transfer_batch(&api, &sender, sub_matches, &units, output).await?;
         }
         Some(("set-default-account", sub_matches)) => {
-            let account = sub_matches
-                .get_one::<String>("account")
-                .expect("account is required");
-            println!("Setting account: {} as default ...", account);
-            db.set_default_account(account)?;
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            let vault = unlock_vault(db, &settings)?;
+            db.set_default_account(&vault, &account)?;
+            output.print(
+                &format!("Default account set to: {}", account),
+                json!({ "default_account": account }),
+            );
+        }
+        Some(("set-label", sub_matches)) => {
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            let label = sub_matches
+                .get_one::<String>("label")
+                .expect("label is required");
+            db.set_label(&account, label)?;
+            output.print(
+                &format!("Label: {} set for account: {}", label, account),
+                json!({ "account": account, "label": label }),
+            );
+        }
+        Some(("clear-label", sub_matches)) => {
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            db.clear_label(&account)?;
+            output.print(
+                &format!("Label cleared for account: {}", account),
+                json!({ "account": account, "label": null }),
+            );
         }
         Some(("set-min-fee", sub_matches)) => {
             let min_fee = *sub_matches
                 .get_one::<u32>("min-fee")
                 .expect("min-fee is required");
             let tx_fee = get_tx_fee(sub_matches, &api).await;
-            println!(
+            unlock_admin(&mut api, &settings)?;
+            let units = get_units(matches, &api).await?;
+            if sub_matches.get_flag("dry-run") {
+                let (ext, preflight) = api
+                    .simulate(
+                        &api.get_admin_key()?,
+                        types::Call::SetMinFee(min_fee),
+                        tx_fee,
+                    )
+                    .await?;
+                return print_dry_run(&ext, preflight, &units, output);
+            }
+            eprintln!(
                 "Setting minimum fee to: {}, tx fee: {} ...",
-                min_fee, tx_fee
+                units.describe(min_fee as u128),
+                units.describe(tx_fee as u128)
             );
-            let result = api.set_min_fee(min_fee, tx_fee).await?;
-            println!("{}", result);
+            let result = api
+                .set_min_fee(min_fee, tx_fee, get_wait_for(sub_matches)?)
+                .await?;
+            print_tx_result(&result, output);
         }
-        Some(("diff", sub_matches)) => {
-            let color = sub_matches
-                .get_one::<String>("color")
-                .map(|s| s.as_str())
-                .expect("defaulted in clap");
-
-            let mut base = sub_matches.get_one::<String>("base").map(|s| s.as_str());
-            let mut head = sub_matches.get_one::<String>("head").map(|s| s.as_str());
-            let mut path = sub_matches.get_one::<String>("path").map(|s| s.as_str());
-            if path.is_none() {
-                path = head;
-                head = None;
-                if path.is_none() {
-                    path = base;
-                    base = None;
+        Some(("upgrade-runtime", sub_matches)) => {
+            let path = sub_matches
+                .get_one::<PathBuf>("code")
+                .expect("code is required");
+            let code = API::read_runtime_code(path)?;
+            let tx_fee = get_tx_fee(sub_matches, &api).await;
+            unlock_admin(&mut api, &settings)?;
+            let before = api.get_runtime_version().await?;
+            let units = get_units(matches, &api).await?;
+            eprintln!("Current runtime version: {}", before);
+            eprintln!(
+                "Upgrading runtime to: {} ({} bytes), tx fee: {} ...",
+                path.display(),
+                code.len(),
+                units.describe(tx_fee as u128)
+            );
+            let result = api
+                .upgrade_runtime(code, tx_fee, get_wait_for(sub_matches)?)
+                .await?;
+            let after = api.get_runtime_version().await?;
+            let mut text = format!(
+                "{}\nRuntime version after upgrade: {}",
+                describe_tx_result(&result),
+                after
+            );
+            if after.spec_version == before.spec_version {
+                text = format!("{}\nSpec version has not changed yet, the new code applies once the tx is included in a block", text);
+            }
+            let mut json = tx_result_json(&result);
+            json["spec_version_before"] = json!(before.spec_version);
+            json["spec_version_after"] = json!(after.spec_version);
+            output.print(&text, json);
+        }
+        Some(("tx", sub_matches)) => match sub_matches.subcommand() {
+            Some(("build", build_matches)) => {
+                let units = get_units(matches, &api).await?;
+                let (out, payload) = build_payload(build_matches, db, &api, &units).await?;
+                tx_file::write(out, &payload)?;
+                output.print(
+                    &format!(
+                        "Unsigned payload: {}\nWritten to: {}",
+                        describe_payload(&payload, &units),
+                        out.display()
+                    ),
+                    json!({ "payload": describe_payload(&payload, &units), "file": out }),
+                );
+            }
+            Some(("sign", sign_matches)) => {
+                let input = sign_matches
+                    .get_one::<PathBuf>("input")
+                    .expect("input is required");
+                let out = sign_matches
+                    .get_one::<PathBuf>("output")
+                    .expect("output is required");
+                let payload: types::ExtrinsicPayload = tx_file::read(input)?;
+                let signer = match sign_matches.get_one::<String>("account") {
+                    Some(account) => KeyStore::get_pub_key(&db.resolve_account(account)?)?,
+                    None => api.get_expected_signer(&payload)?,
+                };
+                if db.is_watch_only(&signer.to_ss58check())? {
+                    return Err(error::Error::Keystore(format!(
+                        "Account: {} is watch-only and can not sign transactions",
+                        signer.to_ss58check()
+                    )));
+                }
+                eprintln!(
+                    "Signing payload: {}",
+                    describe_payload(&payload, &get_offline_units(matches, &settings)?)
+                );
+                eprintln!("Signer: {}", signer.to_ss58check());
+                if api.keystore()?.contains(&signer) || db.account_exists(&signer.to_ss58check())? {
+                    unlock_keystore(&mut api, &settings)?;
                 }
+                let ext = api.sign(&signer, payload)?;
+                tx_file::write(out, &ext)?;
+                output.print(
+                    &format!("Signed extrinsic written to: {}", out.display()),
+                    json!({
+                        "hash": API::extrinsic_hash(&ext),
+                        "signer": signer.to_ss58check(),
+                        "file": out,
+                    }),
+                );
+            }
+            Some(("broadcast", broadcast_matches)) => {
+                let input = broadcast_matches
+                    .get_one::<PathBuf>("input")
+                    .expect("input is required");
+                let ext: types::BasicExtrinsic = tx_file::read(input)?;
+                let units = get_units(matches, &api).await?;
+                eprintln!(
+                    "Broadcasting extrinsic: {} ...",
+                    describe_payload(&ext.0, &units)
+                );
+                let result = api.broadcast(ext, get_wait_for(broadcast_matches)?).await?;
+                print_tx_result(&result, output);
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Handles the network subcommands, which only touch the config file
+fn network(
+    config: &mut Config,
+    config_path: &Path,
+    sub_matches: &ArgMatches,
+    output: &Output,
+) -> Result<(), error::Error> {
+    match sub_matches.subcommand() {
+        Some(("add", add_matches)) => {
+            let name = add_matches
+                .get_one::<String>("name")
+                .expect("name is required");
+            let mut endpoints = config::split_endpoints(
+                add_matches
+                    .get_one::<String>("endpoint")
+                    .ok_or(error::Error::Config(
+                        "The endpoint of the network is required, set it with --endpoint"
+                            .to_owned(),
+                    ))?,
+            );
+            if endpoints.is_empty() {
+                return Err(error::Error::Config(
+                    "The endpoint of the network is empty".to_owned(),
+                ));
+            }
+            if let Some(admin) = add_matches.get_one::<String>("admin") {
+                KeyStore::get_pub_key(admin)?;
+            }
+            let database = match add_matches.get_one::<String>("database") {
+                Some(database) => database.clone(),
+                None => Config::default_database(name)
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            let profile = NetworkProfile {
+                endpoint: endpoints.remove(0),
+                fallbacks: endpoints,
+                database,
+                token_decimals: add_matches.get_one::<u8>("decimals").copied(),
+                token_symbol: add_matches.get_one::<String>("symbol").cloned(),
+                admin: add_matches.get_one::<String>("admin").cloned(),
+            };
+            let text = format!(
+                "Network: {} added, endpoints: {}, database: {}",
+                name,
+                profile.endpoints().join(", "),
+                profile.database
+            );
+            let json = json!({
+                "network": name,
+                "endpoints": profile.endpoints(),
+                "database": profile.database,
+            });
+            config.networks.insert(name.clone(), profile);
+            if config.current.is_none() {
+                config.current = Some(name.clone());
             }
-            let base = base.unwrap_or("stage");
-            let head = head.unwrap_or("worktree");
-            let path = path.unwrap_or("");
-            println!("Diffing {}..{} {} (color={})", base, head, path, color);
+            config.save(config_path)?;
+            output.print(&text, json);
         }
-        Some(("push", sub_matches)) => {
-            println!(
-                "Pushing to {}",
-                sub_matches.get_one::<String>("REMOTE").expect("required")
+        Some(("list", _)) => {
+            let mut lines = vec!["Networks:".to_owned()];
+            let mut networks = Vec::new();
+            for (name, profile) in &config.networks {
+                let current = config.current.as_ref() == Some(name);
+                lines.push(format!(
+                    "{} {}: {} ({})",
+                    if current { "*" } else { " " },
+                    name,
+                    profile.endpoints().join(", "),
+                    profile.database
+                ));
+                networks.push(json!({
+                    "name": name,
+                    "endpoints": profile.endpoints(),
+                    "database": profile.database,
+                    "token_decimals": profile.token_decimals,
+                    "token_symbol": profile.token_symbol,
+                    "admin": profile.admin,
+                    "current": current,
+                }));
+            }
+            output.print(&lines.join("\n"), json!({ "networks": networks }));
+        }
+        Some(("use", use_matches)) => {
+            let name = use_matches
+                .get_one::<String>("name")
+                .expect("name is required");
+            config.profile(Some(name))?;
+            config.current = Some(name.clone());
+            config.save(config_path)?;
+            output.print(
+                &format!("Using network: {}", name),
+                json!({ "network": name }),
             );
         }
-        Some(("add", sub_matches)) => {
-            let paths = sub_matches
-                .get_many::<PathBuf>("PATH")
-                .into_iter()
-                .flatten()
-                .collect::<Vec<_>>();
-            println!("Adding {:?}", paths);
+        Some(("remove", remove_matches)) => {
+            let name = remove_matches
+                .get_one::<String>("name")
+                .expect("name is required");
+            let profile = config
+                .networks
+                .remove(name)
+                .ok_or(error::Error::Config(format!("Unknown network: {}", name)))?;
+            if config.current.as_ref() == Some(name) {
+                config.current = None;
+            }
+            config.save(config_path)?;
+            output.print(
+                &format!(
+                    "Network: {} removed, its database is kept at: {}",
+                    name, profile.database
+                ),
+                json!({ "network": name, "database": profile.database }),
+            );
         }
-        Some(("stash", sub_matches)) => {
-            let stash_command = sub_matches.subcommand().unwrap_or(("push", sub_matches));
-            match stash_command {
-                ("apply", sub_matches) => {
-                    let stash = sub_matches.get_one::<String>("STASH");
-                    println!("Applying {:?}", stash);
-                }
-                ("pop", sub_matches) => {
-                    let stash = sub_matches.get_one::<String>("STASH");
-                    println!("Popping {:?}", stash);
-                }
-                ("push", sub_matches) => {
-                    let message = sub_matches.get_one::<String>("message");
-                    println!("Pushing {:?}", message);
-                }
-                (name, _) => {
-                    unreachable!("Unsupported subcommand `{}`", name)
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Runs the batch of the transfer-batch subcommand, failing if any of its transfers failed
+async fn transfer_batch(
+    api: &API,
+    sender: &str,
+    sub_matches: &ArgMatches,
+    units: &Denomination,
+    output: &Output,
+) -> Result<(), error::Error> {
+    let file = sub_matches
+        .get_one::<PathBuf>("file")
+        .expect("file is required");
+    let results_path = sub_matches
+        .get_one::<PathBuf>("results")
+        .cloned()
+        .unwrap_or_else(|| batch::default_results_path(file));
+    let default_fee = get_tx_fee(sub_matches, api).await;
+    let wait_for = get_wait_for(sub_matches)?;
+    let summary = batch::transfer(
+        api,
+        sender,
+        file,
+        &results_path,
+        default_fee,
+        wait_for,
+        units,
+    )
+    .await?;
+    summary.check(&results_path)?;
+    output.print(
+        &format!(
+            "{} transfers done, results written to: {}",
+            summary.transfers,
+            results_path.display()
+        ),
+        json!({ "transfers": summary.transfers, "results_file": results_path }),
+    );
+    Ok(())
+}
+
+/// Unlocks the wallet vault, creating it the first time. The passphrase is read from WALLET_PASSPHRASE
+/// if set, otherwise it is prompted for
+fn unlock_vault(db: &DB, settings: &Settings) -> Result<Vault, error::Error> {
+    db.open_vault(
+        |prompt| match settings.passphrase() {
+            Some(passphrase) => Ok(passphrase.to_owned()),
+            None => Ok(rpassword::prompt_password(prompt)?),
+        },
+        settings.passphrase().is_none(),
+    )
+}
+
+/// Unlocks the vault and the keystore
+fn unlock_keystore(api: &mut API, settings: &Settings) -> Result<Vault, error::Error> {
+    let vault = unlock_vault(api.db()?, settings)?;
+    api.unlock_wallet(vault.clone())?;
+    Ok(vault)
+}
+
+/// Returns the default account, making sure it is able to sign transactions
+fn get_signing_account(db: &DB) -> Result<String, error::Error> {
+    let account = db.get_default_account()?;
+    if db.is_watch_only(&account)? {
+        return Err(error::Error::Keystore(format!(
+            "Account: {} is watch-only and can not sign transactions",
+            account
+        )));
+    }
+    Ok(account)
+}
+
+/// Builds the unsigned payload of the call of a tx build subcommand, returns it along with the file to write it to
+async fn build_payload<'a>(
+    build_matches: &'a ArgMatches,
+    db: &DB,
+    api: &API,
+    units: &Denomination,
+) -> Result<(&'a PathBuf, types::ExtrinsicPayload), error::Error> {
+    let (name, sub_matches) = build_matches.subcommand().expect("subcommand is required");
+    let out = sub_matches
+        .get_one::<PathBuf>("out")
+        .expect("out is required");
+    let call = match name {
+        "transfer" => {
+            let from = match sub_matches.get_one::<String>("from") {
+                Some(from) => db.resolve_account(from)?,
+                None => db.get_default_account()?,
+            };
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            let amount = units.parse(
+                sub_matches
+                    .get_one::<String>("amount")
+                    .expect("amount is required"),
+            )?;
+            API::transfer_call(&KeyStore::get_pub_key(&from)?, &account, amount)?
+        }
+        "mint" => {
+            let account = db.resolve_account(
+                sub_matches
+                    .get_one::<String>("account")
+                    .expect("account is required"),
+            )?;
+            let amount = units.parse(
+                sub_matches
+                    .get_one::<String>("amount")
+                    .expect("amount is required"),
+            )?;
+            API::mint_call(&account, amount)?
+        }
+        "set-min-fee" => types::Call::SetMinFee(
+            *sub_matches
+                .get_one::<u32>("min-fee")
+                .expect("min-fee is required"),
+        ),
+        _ => unreachable!(),
+    };
+    // mints don't pay a fee, so they have no --txfee
+    let tx_fee = match call {
+        types::Call::Mint(..) => None,
+        _ => sub_matches.get_one::<u32>("txfee").copied(),
+    };
+    let payload = api.build_payload(call, tx_fee).await?;
+    Ok((out, payload))
+}
+
+fn describe_payload(payload: &types::ExtrinsicPayload, units: &Denomination) -> String {
+    let call = match &payload.call {
+        types::Call::Mint(account, amount) => format!(
+            "mint {} for account {}",
+            units.describe(*amount),
+            AccountId32::new(*account).to_ss58check()
+        ),
+        types::Call::Transfer(from, to, amount) => format!(
+            "transfer {} from {} to {}",
+            units.describe(*amount),
+            AccountId32::new(*from).to_ss58check(),
+            AccountId32::new(*to).to_ss58check()
+        ),
+        types::Call::SetMinFee(fee) => format!("set min fee to {}", units.describe(*fee as u128)),
+        types::Call::Upgrade(code) => format!("upgrade runtime to code of {} bytes", code.len()),
+    };
+    format!("{}, tx fee: {}", call, units.describe(payload.fee as u128))
+}
+
+/// Prints the outcome of a dry run, fails with the runtime error the extrinsic would be rejected with
+fn print_dry_run(
+    ext: &types::BasicExtrinsic,
+    preflight: Preflight,
+    units: &Denomination,
+    output: &Output,
+) -> Result<(), error::Error> {
+    let encoded = format!("0x{}", hex::encode(ext.encode()));
+    let mut lines = vec![
+        format!("Dry run: {}", describe_payload(&ext.0, units)),
+        format!("Signer: {}", preflight.signer.to_ss58check()),
+        format!("Encoded extrinsic: {}", encoded),
+        format!(
+            "Current min fee: {}",
+            units.describe(preflight.min_fee as u128)
+        ),
+    ];
+    for balance in &preflight.balances {
+        lines.push(format!(
+            "Balance for account: {} {} -> {}",
+            balance.account.to_ss58check(),
+            units.format(balance.before),
+            units.format(balance.after)
+        ));
+    }
+    if preflight.supply_after != preflight.supply {
+        lines.push(format!(
+            "Total supply: {} -> {}",
+            units.format(preflight.supply),
+            units.format(preflight.supply_after)
+        ));
+    }
+    lines.push(match preflight.error {
+        Some(_) => "Pre-flight checks failed, nothing was submitted".to_owned(),
+        None => "Pre-flight checks passed, nothing was submitted".to_owned(),
+    });
+    let balances: Vec<_> = preflight
+        .balances
+        .iter()
+        .map(|balance| {
+            json!({
+                "account": balance.account.to_ss58check(),
+                "before": balance.before.to_string(),
+                "after": balance.after.to_string(),
+            })
+        })
+        .collect();
+    output.print(
+        &lines.join("\n"),
+        json!({
+            "call": describe_payload(&ext.0, units),
+            "signer": preflight.signer.to_ss58check(),
+            "extrinsic": encoded,
+            "hash": API::extrinsic_hash(ext),
+            "min_fee": preflight.min_fee,
+            "balances": balances,
+            "total_supply_before": preflight.supply.to_string(),
+            "total_supply_after": preflight.supply_after.to_string(),
+            "error": preflight.error.as_ref().map(|runtime_error| json!({
+                "code": runtime_error.exit_code(),
+                "message": runtime_error.to_string(),
+            })),
+        }),
+    );
+    match preflight.error {
+        Some(runtime_error) => {
+            output.set_error_reported();
+            Err(error::Error::Runtime(runtime_error))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Prints the result of a submitted transaction
+fn print_tx_result(result: &TxResult, output: &Output) {
+    output.print(&describe_tx_result(result), tx_result_json(result));
+}
+
+fn describe_tx_result(result: &TxResult) -> String {
+    match &result.status {
+        types::TransactionStatus::InBlock(block) => format!("Tx included in block: {}", block),
+        types::TransactionStatus::Finalized(block) => format!("Tx finalized in block: {}", block),
+        status => format!("Tx submitted: {}, status: {}", result.hash, status),
+    }
+}
+
+fn tx_result_json(result: &TxResult) -> serde_json::Value {
+    json!({
+        "hash": result.hash,
+        "block": result.status.block_hash(),
+        "status": result.status.name(),
+    })
+}
+
+/// Prints a line, or a json object, for every change of the watched values until the subscription
+/// is lost for good
+async fn watch_balance(
+    api: &API,
+    targets: &[StorageItem],
+    units: &Denomination,
+    output: &Output,
+) -> Result<(), error::Error> {
+    let mut watch = api.watch_storage(targets).await?;
+    loop {
+        let update = watch.next().await?;
+        let now = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        for change in update.changes {
+            let target = &targets[change.index];
+            let new = change.new;
+            let mut json = json!({
+                "time": now,
+                "block": update.block,
+                "item": storage_item_name(target),
+                "new": new.to_string(),
+            });
+            if let StorageItem::Balance(account) = target {
+                json["account"] = json!(account.to_ss58check());
+            }
+            match change.old {
+                None => output.print(
+                    &format!(
+                        "{} block: {} {}: {}",
+                        now,
+                        update.block,
+                        describe_item(target),
+                        units.format(new)
+                    ),
+                    json,
+                ),
+                Some(old) => {
+                    let sign = if new >= old { "+" } else { "-" };
+                    let text = format!(
+                        "{} block: {} {}: {} -> {} ({}{})",
+                        now,
+                        update.block,
+                        describe_item(target),
+                        units.format(old),
+                        units.format(new),
+                        sign,
+                        units.format(new.abs_diff(old))
+                    );
+                    json["old"] = json!(old.to_string());
+                    json["delta"] = json!(format!("{}{}", sign, new.abs_diff(old)));
+                    output.print(&text, json);
                 }
             }
         }
-        Some((ext, sub_matches)) => {
-            let args = sub_matches
-                .get_many::<OsString>("")
-                .into_iter()
-                .flatten()
-                .collect::<Vec<_>>();
-            println!("Calling out to {:?} with {:?}", ext, args);
-        }
-        _ => unreachable!(), // If all subcommands are defined above, anything else is unreachabe!()
     }
-    Ok(())
-    // Continued program logic goes here...
+}
+
+fn describe_item(item: &StorageItem) -> String {
+    match item {
+        StorageItem::Balance(account) => format!("Balance for account: {}", account.to_ss58check()),
+        StorageItem::Supply => "Total supply".to_owned(),
+        StorageItem::MinFee => "Min fee".to_owned(),
+    }
+}
+
+fn storage_item_name(item: &StorageItem) -> &'static str {
+    match item {
+        StorageItem::Balance(_) => "balance",
+        StorageItem::Supply => "total_supply",
+        StorageItem::MinFee => "min_fee",
+    }
+}
+
+fn supply_share(balance: u128, supply: u128) -> String {
+    if supply == 0 {
+        return "-".to_owned();
+    }
+    format!("{:.2}%", balance as f64 * 100.0 / supply as f64)
+}
+
+fn record_json(record: &TransactionRecord) -> serde_json::Value {
+    json!({
+        "hash": record.hash,
+        "signer": record.signer,
+        "call": record.call,
+        "counterparty": record.counterparty,
+        "amount": record.amount.map(|amount| amount.to_string()),
+        "fee": record.fee,
+        "submitted_at": record.submitted_at,
+        "status": record.status,
+        "block": record.block_hash,
+        "block_number": record.block_number,
+    })
+}
+
+fn describe_record(record: &TransactionRecord, units: &Denomination) -> String {
+    let amount = record.amount.unwrap_or_default();
+    let counterparty = record.counterparty.as_deref().unwrap_or_default();
+    let call = match record.call.as_str() {
+        "mint" => format!(
+            "mint {} for account {}",
+            units.describe(amount),
+            counterparty
+        ),
+        "transfer" => format!(
+            "transfer {} from {} to {}",
+            units.describe(amount),
+            record.signer,
+            counterparty
+        ),
+        "set-min-fee" => format!("set min fee to {}", units.describe(amount)),
+        call => format!("{} signed by {}", call, record.signer),
+    };
+    let mut line = format!(
+        "{} {}, tx fee: {}, status: {}",
+        record.submitted_at,
+        call,
+        units.describe(record.fee as u128),
+        record.status
+    );
+    if let Some(block_hash) = &record.block_hash {
+        line = format!("{} in block {}", line, block_hash);
+    }
+    format!("{}, tx: {}", line, record.hash)
 }
 
 fn get_operating_account(sub_matches: &ArgMatches, db: &DB) -> Result<String, error::Error> {
     if let Some(account) = sub_matches.get_one::<String>("account") {
-        return Ok(account.to_owned());
+        return db.resolve_account(account);
     }
     let account = db.get_default_account()?;
     Ok(account)
 }
+
+/// The settings given with flags, by the name of the variable they override
+fn get_flag_settings(matches: &ArgMatches) -> Vars {
+    [("ADDR", "endpoint"), ("DATABASE_FILE", "database")]
+        .into_iter()
+        .filter_map(|(name, flag)| {
+            Some((name.to_owned(), matches.get_one::<String>(flag)?.clone()))
+        })
+        .collect()
+}
+
+fn get_rpc_options(matches: &ArgMatches) -> RpcOptions {
+    let seconds =
+        |name: &str| Duration::from_secs(*matches.get_one::<u64>(name).expect("defaulted in clap"));
+    RpcOptions {
+        headers: matches
+            .get_many::<String>("header")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        ca_bundle: matches.get_one::<PathBuf>("ca-bundle").cloned(),
+        connection_timeout: seconds("connect-timeout"),
+        request_timeout: seconds("request-timeout"),
+    }
+}
+
+/// Makes the admin key available for signing, the keystore is unlocked unless it comes from ADMIN_SEED
+fn unlock_admin(api: &mut API, settings: &Settings) -> Result<(), error::Error> {
+    if api.options().admin_seed.is_none() {
+        unlock_keystore(api, settings)?;
+    }
+    Ok(())
+}
+
+/// The denomination amounts are parsed and printed in, base units with --raw
+async fn get_units(matches: &ArgMatches, api: &API) -> Result<Denomination, error::Error> {
+    if matches.get_flag("raw") {
+        return Ok(Denomination::raw());
+    }
+    api.get_denomination().await
+}
+
+/// The denomination for commands meant to run offline, from the settings only as the chain properties
+/// can't be read, amounts are in base units without TOKEN_DECIMALS
+fn get_offline_units(
+    matches: &ArgMatches,
+    settings: &Settings,
+) -> Result<Denomination, error::Error> {
+    if matches.get_flag("raw") {
+        return Ok(Denomination::raw());
+    }
+    Ok(settings.denomination()?.unwrap_or_else(Denomination::raw))
+}
+
+/// Resolves the block given with --at to its hash
+async fn get_at(matches: &ArgMatches, api: &API) -> Result<Option<String>, error::Error> {
+    match matches.get_one::<String>("at") {
+        Some(block) => Ok(Some(api.resolve_block(block).await?)),
+        None => Ok(None),
+    }
+}
+
+fn get_date_arg(sub_matches: &ArgMatches, name: &str) -> Result<Option<NaiveDate>, error::Error> {
+    match sub_matches.get_one::<String>(name) {
+        Some(date) => Ok(Some(NaiveDate::parse_from_str(date, "%Y-%m-%d")?)),
+        None => Ok(None),
+    }
+}
+
 fn get_tx_fee_arg() -> Arg {
-    arg!(--txfee <txfee> "Specify desired tx fee, if not specified the minimum is used")
+    arg!(--txfee <txfee> "Specify desired tx fee in base units of the token, if not specified the minimum is used")
         .value_parser(value_parser!(u32))
 }
 
+fn get_wait_for_arg() -> Arg {
+    arg!(--"wait-for" <stage> "Stage of the transaction lifecycle to wait for before returning")
+        .value_parser(WaitFor::NAMES)
+        .default_value("in-block")
+}
+
+fn get_dry_run_arg() -> Arg {
+    arg!(--"dry-run" "Runs the pre-flight checks and prints the signed extrinsic without submitting it")
+}
+
+fn get_wait_for(sub_matches: &ArgMatches) -> Result<WaitFor, error::Error> {
+    sub_matches
+        .get_one::<String>("wait-for")
+        .expect("defaulted in clap")
+        .parse()
+}
+
 async fn get_tx_fee(sub_matches: &ArgMatches, api: &API) -> u32 {
     if let Some(tx_fee) = sub_matches.get_one::<u32>("txfee") {
         return *tx_fee;
     }
-    api.get_min_fee().await.unwrap_or_default()
-}
-
-fn push_args() -> Vec<clap::Arg> {
-    vec![arg!(-m --message <MESSAGE>)]
+    api.get_min_fee(None).await.unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -334,4 +1511,17 @@ mod tests {
         ]);
         println!("address: {}", key.to_ss58check());
     }
+
+    #[test]
+    fn describes_fees_in_the_denomination() {
+        let payload = types::ExtrinsicPayload::new(types::Call::SetMinFee(25), 10);
+        assert_eq!(
+            describe_payload(&payload, &Denomination::new(6, "UNIT").unwrap()),
+            "set min fee to 0.000025 UNIT, tx fee: 0.00001 UNIT"
+        );
+        assert_eq!(
+            describe_payload(&payload, &Denomination::raw()),
+            "set min fee to 25 base units, tx fee: 10 base units"
+        );
+    }
 }
@@ -0,0 +1,108 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::str::FromStr;
+use wallet_cli::error::Error;
+
+/// Format of the results printed on stdout, progress messages always go to stderr
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "json"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(Error::Config(format!("Unknown output format: {}", s))),
+        }
+    }
+}
+
+pub struct Output {
+    format: OutputFormat,
+    // set once a printed result holds the error the command fails with
+    error_reported: Cell<bool>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Output {
+        Output {
+            format,
+            error_reported: Cell::new(false),
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Prints the result of a command, the text in text mode and the object, on a single line, in json mode
+    pub fn print(&self, text: &str, json: Value) {
+        match self.format {
+            OutputFormat::Text => println!("{}", text),
+            OutputFormat::Json => println!("{}", json),
+        }
+    }
+
+    /// Marks the error of the command as already part of the printed result, in json mode only,
+    /// where a second object would break readers expecting one result per command
+    pub fn set_error_reported(&self) {
+        if self.is_json() {
+            self.error_reported.set(true);
+        }
+    }
+
+    /// Prints the error the command failed with, unless the result already holds it
+    pub fn error(&self, error: &Error) {
+        if self.error_reported.get() {
+            return;
+        }
+        self.print(
+            &format!("Error: {}", error),
+            json!({
+                "error": {
+                    "code": error.exit_code(),
+                    "category": error.category().name(),
+                    "message": error.to_string(),
+                }
+            }),
+        );
+    }
+}
+
+/// Prints the progress the library logs on stderr, so it doesn't mix with the results on stdout
+struct Progress;
+
+impl Log for Progress {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info && metadata.target().starts_with("wallet_cli")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error | Level::Warn => eprintln!("Warning: {}", record.args()),
+            _ => eprintln!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Routes the progress messages of the library to stderr, only the first call has an effect
+pub fn init_progress() {
+    if log::set_logger(&Progress).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
@@ -1,39 +1,410 @@
 use crate::error::Error;
-use jsonrpsee_core::client::{ClientT, Subscription, SubscriptionClientT};
+use crate::tls;
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use jsonrpsee_core::client::{CertificateStore, ClientT, Subscription, SubscriptionClientT};
+use jsonrpsee_core::params::ArrayParams;
 use jsonrpsee_core::rpc_params;
+use jsonrpsee_http_client::{HttpClient, HttpClientBuilder};
 use jsonrpsee_ws_client::{WsClient, WsClientBuilder};
-use std::collections::HashMap;
+use log::warn;
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_rustls::rustls::ClientConfig;
 
+const SCHEMES: [&str; 4] = ["ws", "wss", "http", "https"];
+// rounds over all the endpoints before giving up, the wait between rounds doubles every time
+const CONNECT_ROUNDS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+const CALL_RETRIES: u32 = 3;
+
+/// Options of the connection to the node
+#[derive(Debug, Clone)]
+pub struct RpcOptions {
+    /// Headers sent with the websocket handshake or with every http request, as name: value
+    pub headers: Vec<String>,
+    /// PEM file with the certificates to trust for wss and https endpoints, besides the system ones
+    pub ca_bundle: Option<PathBuf>,
+    /// Timeout of the websocket handshake, http requests are only bounded by the request timeout
+    pub connection_timeout: Duration,
+    pub request_timeout: Duration,
+}
+
+impl Default for RpcOptions {
+    fn default() -> Self {
+        RpcOptions {
+            headers: Vec::new(),
+            ca_bundle: None,
+            connection_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+enum Client {
+    Ws(WsClient),
+    Http(HttpClient),
+    /// Https with the TLS config of a CA bundle, which the http client can't be given
+    Https(WsClient),
+}
+
+impl Client {
+    fn is_connected(&self) -> bool {
+        match self {
+            Client::Ws(client) | Client::Https(client) => client.is_connected(),
+            Client::Http(_) => true,
+        }
+    }
+}
+
+/// Connection to the node, established on first use so that commands that
+/// don't need the node can run offline. When the connection is lost the next
+/// endpoint is tried, waiting longer after every round over all of them
 pub struct RPC {
-    client: WsClient,
+    urls: Vec<String>,
+    options: RpcOptions,
+    /// Trusts the CA bundle of the options, without one the clients use the system certificates
+    tls: Option<Arc<ClientConfig>>,
+    connection: Mutex<Connection>,
+}
+
+/// The endpoint in use and its client, dropped once the connection is lost
+struct Connection {
+    endpoint: usize,
+    client: Option<Arc<Client>>,
 }
 
 impl RPC {
-    pub async fn new(addr: &str) -> Result<RPC, Error> {
-        // let addr = "localhost:9944";
-        let url = format!("ws://{}", addr);
-        let client = WsClientBuilder::default().build(&url).await?;
-        Ok(RPC { client })
+    /// An endpoint without scheme is a ws one, the way ADDR used to be given as host:port
+    pub fn new(endpoints: &[String], options: RpcOptions) -> Result<RPC, Error> {
+        if endpoints.is_empty() {
+            return Err(Error::Config("No endpoint to connect to".to_owned()));
+        }
+        let mut urls = Vec::new();
+        for addr in endpoints {
+            urls.push(match addr.split_once("://") {
+                None => format!("ws://{}", addr),
+                Some((scheme, _)) if SCHEMES.contains(&scheme) => addr.to_owned(),
+                Some((scheme, _)) => {
+                    return Err(Error::Config(format!(
+                        "Unsupported scheme: {} for endpoint: {}, use one of: {}",
+                        scheme,
+                        addr,
+                        SCHEMES.join(", ")
+                    )))
+                }
+            });
+        }
+        let tls = match &options.ca_bundle {
+            Some(ca_bundle) => Some(tls::config(ca_bundle)?),
+            None => None,
+        };
+        Ok(RPC {
+            urls,
+            options,
+            tls,
+            connection: Mutex::new(Connection {
+                endpoint: 0,
+                client: None,
+            }),
+        })
+    }
+
+    /// A connection without endpoint, for commands that may not need the node, connecting fails
+    /// with a config error
+    pub fn without_endpoint(options: RpcOptions) -> RPC {
+        RPC {
+            urls: Vec::new(),
+            options,
+            tls: None,
+            connection: Mutex::new(Connection {
+                endpoint: 0,
+                client: None,
+            }),
+        }
+    }
+
+    /// Http endpoints only serve requests, notifications need every endpoint to be a websocket
+    pub fn supports_subscriptions(&self) -> bool {
+        self.urls.iter().all(|url| url.starts_with("ws"))
+    }
+
+    fn headers(&self) -> Result<HeaderMap, Error> {
+        let mut headers = HeaderMap::new();
+        for header in &self.options.headers {
+            let invalid =
+                || Error::Config(format!("Invalid header: {}, expected name: value", header));
+            let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+            headers.append(
+                HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?,
+                HeaderValue::from_str(value.trim()).map_err(|_| invalid())?,
+            );
+        }
+        Ok(headers)
+    }
+
+    async fn connect(&self, url: &str) -> Result<Client, Error> {
+        if let Some(config) = &self.tls {
+            if url.starts_with("wss") {
+                return Ok(Client::Ws(
+                    tls::connect_ws(
+                        url,
+                        config.clone(),
+                        &self.headers()?,
+                        self.options.connection_timeout,
+                        self.options.request_timeout,
+                    )
+                    .await?,
+                ));
+            }
+            if url.starts_with("https") {
+                return Ok(Client::Https(tls::connect_https(
+                    url,
+                    config.clone(),
+                    self.headers()?,
+                    self.options.request_timeout,
+                )));
+            }
+        }
+        // over http every request opens its own connection, bounded by the request timeout
+        let client = if url.starts_with("http") {
+            Client::Http(
+                HttpClientBuilder::default()
+                    .certificate_store(CertificateStore::Native)
+                    .request_timeout(self.options.request_timeout)
+                    .set_headers(self.headers()?)
+                    .build(url)?,
+            )
+        } else {
+            Client::Ws(
+                WsClientBuilder::default()
+                    .certificate_store(CertificateStore::Native)
+                    .connection_timeout(self.options.connection_timeout)
+                    .request_timeout(self.options.request_timeout)
+                    .set_headers(self.headers()?)
+                    .build(url)
+                    .await?,
+            )
+        };
+        Ok(client)
+    }
+
+    /// The client of the current connection, connecting first if there is none or if it was closed
+    async fn client(&self) -> Result<Arc<Client>, Error> {
+        if self.urls.is_empty() {
+            return Err(Error::Config(
+                "No endpoint configured, use --endpoint, ADDR or a network profile".to_owned(),
+            ));
+        }
+        let mut connection = self.connection.lock().await;
+        if let Some(client) = &connection.client {
+            if client.is_connected() {
+                return Ok(client.clone());
+            }
+            warn!(
+                "Connection to: {} closed, reconnecting ...",
+                self.urls[connection.endpoint]
+            );
+            connection.client = None;
+            connection.endpoint = (connection.endpoint + 1) % self.urls.len();
+        }
+        let mut backoff = INITIAL_BACKOFF;
+        for round in 0..CONNECT_ROUNDS {
+            if round > 0 {
+                warn!("Retrying in {} ms ...", backoff.as_millis());
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            for _ in 0..self.urls.len() {
+                let url = &self.urls[connection.endpoint];
+                match self.connect(url).await {
+                    Ok(client) => {
+                        let client = Arc::new(client);
+                        connection.client = Some(client.clone());
+                        return Ok(client);
+                    }
+                    Err(err) => {
+                        warn!("Unable to connect to: {}, {}", url, err);
+                        connection.endpoint = (connection.endpoint + 1) % self.urls.len();
+                    }
+                }
+            }
+        }
+        Err(Error::Node(format!(
+            "Unable to connect to: {}",
+            self.urls.join(", ")
+        )))
+    }
+
+    /// Drops the client after a connection error so that the next call moves on to the next endpoint,
+    /// unless another call already did
+    async fn disconnect(&self, failed: &Arc<Client>) {
+        let mut connection = self.connection.lock().await;
+        if let Some(client) = &connection.client {
+            if Arc::ptr_eq(client, failed) {
+                connection.client = None;
+                connection.endpoint = (connection.endpoint + 1) % self.urls.len();
+            }
+        }
     }
 
     pub async fn request(&self, method: &str, param: &str) -> Result<Option<String>, Error> {
         // println!("Making rpc call, method:{}, param:{}", method, param);
-        let response: Option<String> = self.client.request(method, rpc_params![param]).await?;
+        let response: Option<String> = self.call(method, rpc_params![param]).await?;
         // println!("response: {:?}", response);
         Ok(response)
     }
 
-    pub async fn subscribe(
+    /// Makes a call with arbitrary params, deserializing the response into the expected type.
+    /// A call that fails because the connection was lost is made again once reconnected, so it
+    /// must be a read or otherwise safe to repeat, submissions go through [`RPC::call_once`]
+    pub async fn call<T: DeserializeOwned>(
         &self,
         method: &str,
-        param: &str,
-    ) -> Result<Subscription<HashMap<String, String>>, Error> {
-        // println!("Subscribing to, method:{}, param:{}", method, param);
-        let subs = self
-            .client
-            .subscribe(method, rpc_params![param], "")
-            .await?;
+        params: ArrayParams,
+    ) -> Result<T, Error> {
+        let mut retries = 0;
+        loop {
+            match self.call_once(method, params.clone()).await {
+                Err(err) if err.is_connection_error() && retries < CALL_RETRIES => {
+                    warn!("Call: {} failed, {}", method, err);
+                    retries += 1;
+                }
+                response => return response,
+            }
+        }
+    }
+
+    /// Makes the call a single time. When the connection is lost the node may or may not have
+    /// handled it, which is up to the caller to find out, the next call uses the next endpoint
+    pub async fn call_once<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: ArrayParams,
+    ) -> Result<T, Error> {
+        let client = self.client().await?;
+        let response = match client.as_ref() {
+            Client::Ws(ws) | Client::Https(ws) => ws.request(method, params).await,
+            Client::Http(http) => http.request(method, params).await,
+        };
+        let response = response.map_err(Error::from);
+        if matches!(&response, Err(err) if err.is_connection_error()) {
+            self.disconnect(&client).await;
+        }
+        response
+    }
+
+    /// Subscribes with arbitrary params, every notification is deserialized into the item type
+    pub async fn subscribe<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: ArrayParams,
+        unsubscribe_method: &str,
+    ) -> Result<Subscription<T>, Error> {
+        // println!("Subscribing to, method:{}, params:{:?}", method, params);
+        // a lost subscription is not made again here, resuming it is up to the caller
+        let subs = match self.client().await?.as_ref() {
+            Client::Ws(client) => {
+                client
+                    .subscribe(method, params, unsubscribe_method)
+                    .await?
+            }
+            Client::Http(_) | Client::Https(_) => {
+                return Err(Error::Node(format!(
+                    "{} needs a subscription, which http endpoints don't support, use a ws or wss endpoint",
+                    method
+                )))
+            }
+        };
         // println!("response: {:?}\n\n\n", subs);
         Ok(subs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn endpoints(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| url.to_string()).collect()
+    }
+
+    fn with_headers(headers: &[&str]) -> RPC {
+        let options = RpcOptions {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            ..RpcOptions::default()
+        };
+        RPC::new(&endpoints(&["wss://rpc.example.com"]), options).unwrap()
+    }
+
+    #[test]
+    fn endpoints_default_to_ws() {
+        let rpc = RPC::new(
+            &endpoints(&["127.0.0.1:9944", "wss://rpc.example.com:443"]),
+            RpcOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            rpc.urls,
+            ["ws://127.0.0.1:9944", "wss://rpc.example.com:443"]
+        );
+        assert!(rpc.supports_subscriptions());
+
+        let rpc = RPC::new(
+            &endpoints(&["ws://127.0.0.1:9944", "https://rpc.example.com"]),
+            RpcOptions::default(),
+        )
+        .unwrap();
+        assert!(!rpc.supports_subscriptions());
+
+        assert!(matches!(
+            RPC::new(&endpoints(&["tcp://127.0.0.1:9944"]), RpcOptions::default()),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            RPC::new(&[], RpcOptions::default()),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn loads_the_ca_bundle() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let with_bundle = |path: PathBuf| RpcOptions {
+            ca_bundle: Some(path),
+            ..RpcOptions::default()
+        };
+        let url = endpoints(&["wss://rpc.example.com"]);
+        assert!(matches!(
+            RPC::new(&url, with_bundle(dir.path().join("missing.pem"))),
+            Err(Error::Config(_))
+        ));
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "no certificate").unwrap();
+        assert!(matches!(
+            RPC::new(&url, with_bundle(empty)),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn parses_headers() {
+        let headers = with_headers(&["Authorization: Bearer token", "X-Api-Key:key"])
+            .headers()
+            .unwrap();
+        assert_eq!(headers["authorization"], "Bearer token");
+        assert_eq!(headers["x-api-key"], "key");
+
+        for invalid in ["no separator", "bad name: value", "X-Api-Key: line\nbreak"] {
+            assert!(
+                matches!(with_headers(&[invalid]).headers(), Err(Error::Config(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::error::Error;
+use futures_util::io::{BufReader, BufWriter};
+use http::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use http::Uri;
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+use jsonrpsee_core::async_trait;
+use jsonrpsee_core::client::{
+    Client, ClientBuilder, ReceivedMessage, TransportReceiverT, TransportSenderT,
+};
+use log::warn;
+use soketto::handshake::client::{Client as Handshake, Header, ServerResponse};
+use soketto::{connection, Data, Incoming};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+type Stream = BufReader<BufWriter<Compat<TlsStream<TcpStream>>>>;
+
+/// The TLS config trusting the system certificates and those of the PEM bundle
+pub fn config(ca_bundle: &Path) -> Result<Arc<ClientConfig>, Error> {
+    let mut roots = RootCertStore::empty();
+    match rustls_native_certs::load_native_certs() {
+        // like the rpc clients, system certificates rustls can't use are skipped
+        Ok(certs) => {
+            for cert in certs {
+                let _ = roots.add(&Certificate(cert.0));
+            }
+        }
+        Err(err) => warn!("Unable to load the system certificates, {}", err),
+    }
+    let unreadable = |err: io::Error| {
+        Error::Config(format!(
+            "CA bundle: {} can't be read, {}",
+            ca_bundle.display(),
+            err
+        ))
+    };
+    let file = File::open(ca_bundle).map_err(unreadable)?;
+    let certs = rustls_pemfile::certs(&mut io::BufReader::new(file)).map_err(unreadable)?;
+    if certs.is_empty() {
+        return Err(Error::Config(format!(
+            "CA bundle: {} holds no PEM certificate",
+            ca_bundle.display()
+        )));
+    }
+    for cert in certs {
+        roots.add(&Certificate(cert)).map_err(|err| {
+            Error::Config(format!(
+                "Invalid certificate in CA bundle: {}, {}",
+                ca_bundle.display(),
+                err
+            ))
+        })?;
+    }
+    Ok(Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ))
+}
+
+/// Opens a wss connection with the given TLS config, the handshake is bounded by the timeout
+pub async fn connect_ws(
+    url: &str,
+    config: Arc<ClientConfig>,
+    headers: &HeaderMap,
+    connection_timeout: Duration,
+    request_timeout: Duration,
+) -> Result<Client, Error> {
+    let (sender, receiver) =
+        tokio::time::timeout(connection_timeout, handshake(url, config, headers))
+            .await
+            .map_err(|_| Error::Node(format!("Connection to: {} timed out", url)))??;
+    Ok(ClientBuilder::default()
+        .request_timeout(request_timeout)
+        .build_with_tokio(WsSender(sender), WsReceiver(receiver)))
+}
+
+async fn handshake(
+    url: &str,
+    config: Arc<ClientConfig>,
+    headers: &HeaderMap,
+) -> Result<(connection::Sender<Stream>, connection::Receiver<Stream>), Error> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|_| Error::Config(format!("Invalid endpoint: {}", url)))?;
+    let host = uri
+        .host()
+        .ok_or_else(|| Error::Config(format!("No host in endpoint: {}", url)))?;
+    let port = uri.port_u16().unwrap_or(443);
+    let host_header = format!("{}:{}", host, port);
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    let server_name = ServerName::try_from(host)
+        .map_err(|_| Error::Config(format!("Invalid host: {} in endpoint: {}", host, url)))?;
+    let socket = TcpStream::connect((host, port)).await?;
+    let stream = TlsConnector::from(config)
+        .connect(server_name, socket)
+        .await?;
+    let mut client = Handshake::new(
+        BufReader::new(BufWriter::new(stream.compat())),
+        &host_header,
+        path,
+    );
+    let headers: Vec<_> = headers
+        .iter()
+        .map(|(name, value)| Header {
+            name: name.as_str(),
+            value: value.as_bytes(),
+        })
+        .collect();
+    client.set_headers(&headers);
+    let response = client
+        .handshake()
+        .await
+        .map_err(|err| Error::Node(format!("Handshake with: {} failed, {}", url, err)))?;
+    match response {
+        ServerResponse::Accepted { .. } => Ok(client.into_builder().finish()),
+        ServerResponse::Rejected { status_code } => Err(Error::Node(format!(
+            "Connection to: {} rejected with status: {}",
+            url, status_code
+        ))),
+        ServerResponse::Redirect { location, .. } => Err(Error::Node(format!(
+            "Connection to: {} redirected to: {}, which is not followed",
+            url, location
+        ))),
+    }
+}
+
+/// An https client with the given TLS config. Every request is posted on its own and its answer
+/// handed to the async client, which matches it with the call
+pub fn connect_https(
+    url: &str,
+    config: Arc<ClientConfig>,
+    headers: HeaderMap,
+    request_timeout: Duration,
+) -> Client {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(ClientConfig::clone(&config))
+        .https_only()
+        .enable_http1()
+        .build();
+    let (answers, receiver) = mpsc::unbounded_channel();
+    let sender = HttpSender {
+        client: hyper::Client::builder().build(connector),
+        url: url.to_owned(),
+        headers,
+        answers,
+    };
+    ClientBuilder::default()
+        .request_timeout(request_timeout)
+        .build_with_tokio(sender, HttpReceiver(receiver))
+}
+
+struct WsSender(connection::Sender<Stream>);
+
+struct WsReceiver(connection::Receiver<Stream>);
+
+#[async_trait]
+impl TransportSenderT for WsSender {
+    type Error = connection::Error;
+
+    async fn send(&mut self, body: String) -> Result<(), Self::Error> {
+        self.0.send_text(body).await?;
+        self.0.flush().await
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.0.close().await
+    }
+}
+
+#[async_trait]
+impl TransportReceiverT for WsReceiver {
+    type Error = connection::Error;
+
+    async fn receive(&mut self) -> Result<ReceivedMessage, Self::Error> {
+        loop {
+            let mut message = Vec::new();
+            match self.0.receive(&mut message).await? {
+                Incoming::Data(Data::Text(_)) => {
+                    return String::from_utf8(message)
+                        .map(ReceivedMessage::Text)
+                        .map_err(|err| connection::Error::Utf8(err.utf8_error()))
+                }
+                Incoming::Data(Data::Binary(_)) => return Ok(ReceivedMessage::Bytes(message)),
+                Incoming::Pong(_) => return Ok(ReceivedMessage::Pong),
+                _ => continue,
+            }
+        }
+    }
+}
+
+struct HttpSender {
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    url: String,
+    headers: HeaderMap,
+    answers: mpsc::UnboundedSender<String>,
+}
+
+struct HttpReceiver(mpsc::UnboundedReceiver<String>);
+
+fn transport_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[async_trait]
+impl TransportSenderT for HttpSender {
+    type Error = io::Error;
+
+    async fn send(&mut self, body: String) -> Result<(), Self::Error> {
+        let mut request = hyper::Request::post(self.url.as_str())
+            .body(hyper::Body::from(body))
+            .map_err(transport_error)?;
+        request.headers_mut().extend(self.headers.clone());
+        request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(transport_error)?;
+        if !response.status().is_success() {
+            return Err(transport_error(format!(
+                "Request rejected with status: {}",
+                response.status()
+            )));
+        }
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(transport_error)?;
+        let answer = String::from_utf8(body.to_vec()).map_err(transport_error)?;
+        self.answers.send(answer).map_err(transport_error)
+    }
+}
+
+#[async_trait]
+impl TransportReceiverT for HttpReceiver {
+    type Error = io::Error;
+
+    async fn receive(&mut self) -> Result<ReceivedMessage, Self::Error> {
+        self.0
+            .recv()
+            .await
+            .map(ReceivedMessage::Text)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::ConnectionAborted, "Connection closed"))
+    }
+}
@@ -0,0 +1,21 @@
+use crate::error::Error;
+use hex::FromHex;
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use std::fs;
+use std::path::Path;
+
+// Unsigned payloads and signed extrinsics are moved between the online and the offline
+// machine as files holding their hex encoded SCALE representation
+
+pub fn write<T: Encode>(path: &Path, value: &T) -> Result<(), Error> {
+    fs::write(path, format!("0x{}\n", hex::encode(value.encode())))?;
+    Ok(())
+}
+
+pub fn read<T: Decode>(path: &Path) -> Result<T, Error> {
+    let content = fs::read_to_string(path)?;
+    let content = content.trim();
+    let encoded = <Vec<u8>>::from_hex(content.strip_prefix("0x").unwrap_or(content))?;
+    let value = T::decode_all(&mut &encoded[..])?;
+    Ok(value)
+}
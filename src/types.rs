@@ -1,6 +1,10 @@
-use parity_scale_codec::{Decode, Encode};
-use sp_runtime::serde::{Deserialize, Serialize};
-use sp_runtime::traits::Extrinsic;
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use serde::Deserialize;
+use sp_core::crypto::AccountId32;
+use sp_core::sr25519;
+use sp_runtime::traits::{Extrinsic, IdentifyAccount, Verify};
+use sp_runtime::{MultiSignature, MultiSigner};
+use std::fmt;
 
 // #[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
@@ -13,6 +17,38 @@ pub enum Error {
     FeeTooLow,
 }
 
+impl Error {
+    /// The runtime reports its errors as custom transaction validity errors, using the index of the variant as code
+    pub fn from_custom_code(code: u8) -> Option<Error> {
+        Error::decode(&mut &[code][..]).ok()
+    }
+
+    /// Exit code used by the cli when the transaction was rejected with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoSignature => 10,
+            Error::NoAdminKey => 11,
+            Error::InvalidSignature => 12,
+            Error::SupplyOverflow => 13,
+            Error::BalanceTooLow => 14,
+            Error::FeeTooLow => 15,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoSignature => write!(f, "The transaction is not signed"),
+            Error::NoAdminKey => write!(f, "The operation requires the admin key, but the transaction was not signed by the admin account"),
+            Error::InvalidSignature => write!(f, "The transaction signature is invalid"),
+            Error::SupplyOverflow => write!(f, "Minting the amount would overflow the total supply"),
+            Error::BalanceTooLow => write!(f, "The balance is too low to pay for the amount and the fee"),
+            Error::FeeTooLow => write!(f, "The fee is lower than the minimum fee"),
+        }
+    }
+}
+
 // #[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub enum Call {
@@ -35,6 +71,9 @@ impl ExtrinsicPayload {
     }
 }
 
+/// Signature of an extrinsic as the runtime takes it. Sr25519 keys use the raw 64 byte signature and
+/// 32 byte public key. The other schemes would be mistaken for it with the same lengths, so their
+/// fields hold the SCALE encoded MultiSignature and MultiSigner, whose first byte tags the scheme
 // #[cfg_attr(feature = "std", derive(Serialize, Deserialize, parity_util_mem::MallocSizeOf))]
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct Signature {
@@ -43,24 +82,46 @@ pub struct Signature {
 }
 
 impl Signature {
-    // pub fn new(sig: [u8;64], origin:) -> Self{
-    // 	Signature(sig.to_vec())
-    // }
+    pub fn new(signature: MultiSignature, origin: MultiSigner) -> Signature {
+        match (signature, origin) {
+            (MultiSignature::Sr25519(signature), MultiSigner::Sr25519(origin)) => Signature {
+                signature: signature.0.to_vec(),
+                origin: origin.0.to_vec(),
+            },
+            (signature, origin) => Signature {
+                signature: signature.encode(),
+                origin: origin.encode(),
+            },
+        }
+    }
 
-    pub fn origin_as_array(&self) -> [u8; 32] {
-        Signature::to_array(self.origin.clone())
+    /// The signature and the signer with their scheme, None if the fields hold neither layout
+    pub fn parts(&self) -> Option<(MultiSignature, MultiSigner)> {
+        if let (Ok(signature), Ok(origin)) = (
+            <[u8; 64]>::try_from(&self.signature[..]),
+            <[u8; 32]>::try_from(&self.origin[..]),
+        ) {
+            return Some((
+                sr25519::Signature::from_raw(signature).into(),
+                sr25519::Public::from_raw(origin).into(),
+            ));
+        }
+        Some((
+            MultiSignature::decode_all(&mut &self.signature[..]).ok()?,
+            MultiSigner::decode_all(&mut &self.origin[..]).ok()?,
+        ))
     }
 
-    fn to_array<T, const N: usize>(v: Vec<T>) -> [T; N] {
-        v.try_into().unwrap_or_else(|v: Vec<T>| {
-            panic!("Expected a Vec of length {} but it was {}", N, v.len())
-        })
+    /// The account id of the signer, as used in the calls
+    pub fn origin_account(&self) -> Option<AccountId32> {
+        self.parts().map(|(_, origin)| origin.into_account())
     }
-}
 
-impl Into<sp_core::sr25519::Signature> for Signature {
-    fn into(self) -> sp_core::sr25519::Signature {
-        sp_core::sr25519::Signature(Signature::to_array(self.signature))
+    pub fn verify(&self, payload: &[u8]) -> bool {
+        match self.parts() {
+            Some((signature, origin)) => signature.verify(payload, &origin.into_account()),
+            None => false,
+        }
     }
 }
 
@@ -84,3 +145,152 @@ impl sp_runtime::traits::Extrinsic for BasicExtrinsic {
         Some(Self(data, signature))
     }
 }
+
+/// Status of a transaction in the pool as reported by author_submitAndWatchExtrinsic
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus {
+    Future,
+    Ready,
+    Broadcast(Vec<String>),
+    InBlock(String),
+    Retracted(String),
+    FinalityTimeout(String),
+    Finalized(String),
+    Usurped(String),
+    Dropped,
+    Invalid,
+}
+
+impl TransactionStatus {
+    /// Short name of the status, as recorded in the transactions ledger
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionStatus::Future => "future",
+            TransactionStatus::Ready => "ready",
+            TransactionStatus::Broadcast(_) => "broadcast",
+            TransactionStatus::InBlock(_) => "in-block",
+            TransactionStatus::Retracted(_) => "retracted",
+            TransactionStatus::FinalityTimeout(_) => "finality-timeout",
+            TransactionStatus::Finalized(_) => "finalized",
+            TransactionStatus::Usurped(_) => "usurped",
+            TransactionStatus::Dropped => "dropped",
+            TransactionStatus::Invalid => "invalid",
+        }
+    }
+
+    /// The block the transaction is included in, if any
+    pub fn block_hash(&self) -> Option<&str> {
+        match self {
+            TransactionStatus::InBlock(block)
+            | TransactionStatus::FinalityTimeout(block)
+            | TransactionStatus::Finalized(block) => Some(block),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionStatus::Future => write!(f, "future, waiting for a previous transaction"),
+            TransactionStatus::Ready => write!(f, "ready"),
+            TransactionStatus::Broadcast(peers) => write!(f, "broadcast to {} peers", peers.len()),
+            TransactionStatus::InBlock(block) => write!(f, "included in block {}", block),
+            TransactionStatus::Retracted(block) => write!(f, "block {} retracted", block),
+            TransactionStatus::FinalityTimeout(block) => {
+                write!(f, "finality timeout in block {}", block)
+            }
+            TransactionStatus::Finalized(block) => write!(f, "finalized in block {}", block),
+            TransactionStatus::Usurped(hash) => write!(f, "usurped by {}", hash),
+            TransactionStatus::Dropped => write!(f, "dropped"),
+            TransactionStatus::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+/// Notification of state_subscribeStorage, the hex encoded keys that changed in the block along with
+/// their new hex encoded values, None for removed values
+#[derive(Deserialize, Debug)]
+pub struct StorageChangeSet {
+    pub block: String,
+    pub changes: Vec<(String, Option<String>)>,
+}
+
+/// Response of state_getReadProof, the hex encoded trie nodes proving the values at the block
+#[derive(Deserialize, Debug)]
+pub struct ReadProof {
+    pub at: String,
+    pub proof: Vec<String>,
+}
+
+/// Full header, used to check the state root of a block against its hash
+pub type VerifiableHeader = sp_runtime::generic::Header<u64, sp_runtime::traits::BlakeTwo256>;
+
+/// Block as returned by chain_getBlock, extrinsics are hex encoded
+#[derive(Deserialize, Debug)]
+pub struct SignedBlock {
+    pub block: Block,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Block {
+    pub header: Header,
+    pub extrinsics: Vec<String>,
+}
+
+/// The header fields the wallet uses, the block number is hex encoded
+#[derive(Deserialize, Debug)]
+pub struct Header {
+    pub number: String,
+}
+
+impl Header {
+    pub fn number(&self) -> Option<u64> {
+        u64::from_str_radix(self.number.trim_start_matches("0x"), 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::key_store::{KeyPair, Scheme};
+
+    #[test]
+    fn signatures_round_trip_for_every_scheme() {
+        let payload = ExtrinsicPayload::new(Call::SetMinFee(10), 0).encode();
+        for name in Scheme::NAMES {
+            let pair = KeyPair::from_seed(name.parse().unwrap(), "//Alice").unwrap();
+            let signature = Signature::new(pair.sign(&payload), pair.signer());
+            let decoded = Signature::decode_all(&mut &signature.encode()[..]).unwrap();
+            assert!(decoded.verify(&payload), "{}", name);
+            assert!(!decoded.verify(b"another payload"), "{}", name);
+            assert_eq!(decoded.origin_account(), Some(pair.account()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn sr25519_signatures_keep_the_raw_layout() {
+        let pair = KeyPair::from_seed(Scheme::Sr25519, "//Alice").unwrap();
+        let signature = Signature::new(pair.sign(b"payload"), pair.signer());
+        assert_eq!(signature.signature.len(), 64);
+        assert_eq!(signature.origin, <[u8; 32]>::from(pair.account()));
+
+        // ed25519 has the same lengths, the scheme tag sets it apart
+        let pair = KeyPair::from_seed(Scheme::Ed25519, "//Alice").unwrap();
+        let signature = Signature::new(pair.sign(b"payload"), pair.signer());
+        assert_eq!(
+            (signature.signature.len(), signature.origin.len()),
+            (65, 33)
+        );
+        assert!(signature.verify(b"payload"));
+
+        let garbage = Signature {
+            signature: vec![0; 10],
+            origin: vec![0; 10],
+        };
+        assert!(garbage.origin_account().is_none());
+        assert!(!garbage.verify(b"payload"));
+    }
+}
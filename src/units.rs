@@ -0,0 +1,181 @@
+use crate::error::Error;
+use serde_json::{Map, Value};
+
+// 10^38 is the largest power of ten that fits in a u128
+const MAX_DECIMALS: u8 = 38;
+pub const DEFAULT_SYMBOL: &str = "UNIT";
+
+/// Denomination of the token: amounts are held on chain in base units, one token being
+/// 10^decimals base units
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Denomination {
+    decimals: u8,
+    symbol: Option<String>,
+}
+
+impl Denomination {
+    pub fn new(decimals: u8, symbol: &str) -> Result<Denomination, Error> {
+        if decimals > MAX_DECIMALS {
+            return Err(Error::Config(format!(
+                "Token decimals: {} is more than the supported {}",
+                decimals, MAX_DECIMALS
+            )));
+        }
+        Ok(Denomination {
+            decimals,
+            symbol: Some(symbol.to_owned()),
+        })
+    }
+
+    /// Amounts in base units, without symbol
+    pub fn raw() -> Denomination {
+        Denomination {
+            decimals: 0,
+            symbol: None,
+        }
+    }
+
+    /// Reads the tokenDecimals and tokenSymbol chain properties, a chain without them uses base units
+    pub fn from_properties(properties: &Map<String, Value>) -> Result<Denomination, Error> {
+        // chains with several tokens report arrays, the first one is the native token
+        let first = |value: &Value| match value {
+            Value::Array(values) => values.first().cloned(),
+            value => Some(value.clone()),
+        };
+        let decimals = match properties.get("tokenDecimals").and_then(first) {
+            Some(decimals) => decimals
+                .as_u64()
+                .and_then(|decimals| u8::try_from(decimals).ok())
+                .ok_or(Error::Config(format!(
+                    "Invalid token decimals: {}",
+                    decimals
+                )))?,
+            None => 0,
+        };
+        let symbol = properties
+            .get("tokenSymbol")
+            .and_then(first)
+            .and_then(|symbol| symbol.as_str().map(str::to_owned))
+            .unwrap_or(DEFAULT_SYMBOL.to_owned());
+        Denomination::new(decimals, &symbol)
+    }
+
+    /// Parses an amount like 12.5 or 12.5 UNIT into base units, rejecting amounts that
+    /// have more decimals than the token or that don't fit in a u128
+    pub fn parse(&self, input: &str) -> Result<u128, Error> {
+        let invalid = || Error::Config(format!("Invalid amount: {}", input));
+        let mut parts = input.split_whitespace();
+        let number = parts.next().ok_or_else(invalid)?;
+        if let Some(symbol) = parts.next() {
+            match &self.symbol {
+                Some(expected) if symbol.eq_ignore_ascii_case(expected) => {}
+                Some(expected) => {
+                    return Err(Error::Config(format!(
+                        "Amount: {} is not in {}",
+                        input, expected
+                    )))
+                }
+                None => return Err(invalid()),
+            }
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let fraction = fraction.trim_end_matches('0');
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (integer.is_empty() && fraction.is_empty())
+            || !is_digits(integer)
+            || !is_digits(fraction)
+        {
+            return Err(invalid());
+        }
+        if fraction.len() > self.decimals as usize {
+            return Err(Error::Config(format!(
+                "Amount: {} has more than the {} decimals of the token",
+                input, self.decimals
+            )));
+        }
+        let overflow = || Error::Config(format!("Amount: {} is too large", input));
+        let scale = 10u128.pow(self.decimals as u32 - fraction.len() as u32);
+        let integer: u128 = match integer {
+            "" => 0,
+            integer => integer.parse().map_err(|_| overflow())?,
+        };
+        let fraction: u128 = match fraction {
+            "" => 0,
+            fraction => fraction.parse().map_err(|_| overflow())?,
+        };
+        integer
+            .checked_mul(self.unit())
+            .and_then(|amount| amount.checked_add(fraction * scale))
+            .ok_or_else(overflow)
+    }
+
+    /// Formats an amount of base units, without trailing zeros in the decimals
+    pub fn format(&self, amount: u128) -> String {
+        let integer = amount / self.unit();
+        let fraction = amount % self.unit();
+        let mut formatted = integer.to_string();
+        if fraction > 0 {
+            let fraction = format!("{:0width$}", fraction, width = self.decimals as usize);
+            formatted = format!("{}.{}", formatted, fraction.trim_end_matches('0'));
+        }
+        match &self.symbol {
+            Some(symbol) => format!("{} {}", formatted, symbol),
+            None => formatted,
+        }
+    }
+
+    /// Formats an amount along with its unit, amounts without symbol are labelled as base units
+    pub fn describe(&self, amount: u128) -> String {
+        match &self.symbol {
+            Some(_) => self.format(amount),
+            None => format!("{} base units", self.format(amount)),
+        }
+    }
+
+    fn unit(&self) -> u128 {
+        10u128.pow(self.decimals as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_and_format_amounts() {
+        let units = Denomination::new(6, "UNIT").unwrap();
+        assert_eq!(units.parse("12.5").unwrap(), 12_500_000);
+        assert_eq!(units.parse("12.5 UNIT").unwrap(), 12_500_000);
+        assert_eq!(units.parse("0.000001").unwrap(), 1);
+        assert_eq!(units.parse(".5").unwrap(), 500_000);
+        assert_eq!(units.parse("3.1000000").unwrap(), 3_100_000);
+        assert_eq!(units.format(12_500_000), "12.5 UNIT");
+        assert_eq!(units.format(3), "0.000003 UNIT");
+        assert_eq!(units.format(7_000_000), "7 UNIT");
+        assert_eq!(units.describe(7_000_000), "7 UNIT");
+
+        assert!(units.parse("0.0000001").is_err());
+        assert!(units.parse("12.5 DOT").is_err());
+        assert!(units.parse("-1").is_err());
+        assert!(units.parse("1.2.3").is_err());
+        assert!(units.parse(".").is_err());
+        assert!(units
+            .parse("340282366920938463463374607431768211455")
+            .is_err());
+
+        let raw = Denomination::raw();
+        assert_eq!(
+            raw.parse("340282366920938463463374607431768211455")
+                .unwrap(),
+            u128::MAX
+        );
+        assert_eq!(raw.format(u128::MAX), u128::MAX.to_string());
+        assert_eq!(raw.describe(12), "12 base units");
+        assert!(raw.parse("1.5").is_err());
+        assert!(raw.parse("1 UNIT").is_err());
+    }
+}
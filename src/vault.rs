@@ -0,0 +1,113 @@
+use crate::error::Error;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use hex::FromHex;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+// Argon2id cost parameters for newly created vaults: 64 MiB, 3 passes, 1 lane
+const DEFAULT_M_COST: u32 = 64 * 1024;
+const DEFAULT_T_COST: u32 = 3;
+const DEFAULT_P_COST: u32 = 1;
+// Known plaintext stored encrypted next to the kdf params, used to detect a wrong passphrase
+const CHECK_VALUE: &str = "wallet-cli-vault";
+
+/// Parameters used to derive the vault key from the passphrase, stored in the db
+pub struct KdfParams {
+    pub salt: Vec<u8>,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    pub fn generate() -> KdfParams {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams {
+            salt,
+            m_cost: DEFAULT_M_COST,
+            t_cost: DEFAULT_T_COST,
+            p_cost: DEFAULT_P_COST,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Vault {
+    cipher: XChaCha20Poly1305,
+}
+
+impl Vault {
+    pub fn new(passphrase: &str, params: &KdfParams) -> Result<Vault, Error> {
+        let argon2 = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_LEN))?,
+        );
+        let mut key = [0u8; KEY_LEN];
+        argon2.hash_password_into(passphrase.as_bytes(), &params.salt, &mut key)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key));
+        Ok(Vault { cipher })
+    }
+
+    /// Encrypts the value with a fresh random nonce, returns the hex encoded nonce followed by the ciphertext
+    pub fn encrypt(&self, value: &str) -> Result<String, Error> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), value.as_bytes())?;
+        Ok(hex::encode([&nonce[..], &ciphertext[..]].concat()))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<String, Error> {
+        let encrypted = <Vec<u8>>::from_hex(encrypted)?;
+        if encrypted.len() < NONCE_LEN {
+            return Err(Error::Decode("Encrypted value is too short".to_owned()));
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+        let value = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                Error::Keystore("Unable to decrypt value, wrong passphrase?".to_owned())
+            })?;
+        String::from_utf8(value)
+            .map_err(|_| Error::Decode("Decrypted value is not valid utf8".to_owned()))
+    }
+
+    pub fn encrypt_check_value(&self) -> Result<String, Error> {
+        self.encrypt(CHECK_VALUE)
+    }
+
+    pub fn verify_check_value(&self, encrypted: &str) -> Result<(), Error> {
+        match self.decrypt(encrypted) {
+            Ok(value) if value == CHECK_VALUE => Ok(()),
+            _ => Err(Error::Keystore("Wrong passphrase".to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_roundtrip() {
+        let params = KdfParams::generate();
+        let vault = Vault::new("passphrase", &params).unwrap();
+        let encrypted = vault.encrypt("some mnemonic").unwrap();
+        assert_eq!(vault.decrypt(&encrypted).unwrap(), "some mnemonic");
+
+        let check_value = vault.encrypt_check_value().unwrap();
+        let wrong_vault = Vault::new("wrong passphrase", &params).unwrap();
+        assert!(wrong_vault.verify_check_value(&check_value).is_err());
+        assert!(wrong_vault.decrypt(&encrypted).is_err());
+    }
+}
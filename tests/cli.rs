@@ -0,0 +1,789 @@
+//! End to end tests of the commands, running the wallet binary against the mock node
+
+mod common;
+
+use common::{address, MockNode, GENESIS_MIN_FEE};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const ADMIN: &str = "//Alice";
+const PASSPHRASE: &str = "correct horse battery staple";
+// exit codes documented in the help of the wallet
+const CONFIG_ERROR: i32 = 2;
+const DATABASE_ERROR: i32 = 4;
+const KEYSTORE_ERROR: i32 = 5;
+const ADDRESS_ERROR: i32 = 6;
+const RPC_ERROR: i32 = 7;
+const TRANSACTION_ERROR: i32 = 8;
+const DECODE_ERROR: i32 = 9;
+const NO_ADMIN_KEY: i32 = 11;
+const BALANCE_TOO_LOW: i32 = 14;
+const FEE_TOO_LOW: i32 = 15;
+
+/// A wallet with its own database and config file, connected to a mock node
+struct Wallet<'a> {
+    node: &'a MockNode,
+    dir: TempDir,
+}
+
+impl<'a> Wallet<'a> {
+    fn new(node: &'a MockNode) -> Wallet<'a> {
+        Wallet {
+            node,
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    /// The wallet command with json output, isolated from the environment of the test run
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_wallet-cli"));
+        command
+            .args(["--output", "json"])
+            .args(args)
+            .env_clear()
+            .current_dir(self.dir.path())
+            .env("ADDR", self.node.ws_url())
+            .env("DATABASE_FILE", self.path("wallet.sql"))
+            .env("WALLET_CONFIG", self.path("config.toml"))
+            .env("WALLET_PASSPHRASE", PASSPHRASE)
+            .env("ADMIN_SEED", ADMIN);
+        command
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.path().join(name).to_string_lossy().into_owned()
+    }
+
+    /// Runs the command, returns its exit code and the last json object it printed
+    fn exec(&self, mut command: Command) -> (i32, Value) {
+        let output = command.output().expect("wallet runs");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let json = stdout
+            .lines()
+            .last()
+            .and_then(|line| serde_json::from_str(line).ok())
+            .unwrap_or_else(|| panic!("no json output\nstdout: {}\nstderr: {}", stdout, stderr));
+        (output.status.code().expect("exited"), json)
+    }
+
+    fn ok(&self, args: &[&str]) -> Value {
+        let (code, json) = self.exec(self.command(args));
+        assert_eq!(code, 0, "{:?} failed with: {}", args, json);
+        json
+    }
+
+    fn fails(&self, args: &[&str], expected: i32) -> Value {
+        let (code, json) = self.exec(self.command(args));
+        assert_eq!(code, expected, "{:?} returned: {}", args, json);
+        json
+    }
+
+    fn add_account(&self, suri: &str) -> String {
+        self.ok(&["add-account", suri])["account"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    }
+}
+
+fn start_node() -> MockNode {
+    MockNode::start(&common::account(ADMIN))
+}
+
+fn tokens(amount: u128) -> u128 {
+    amount * 10u128.pow(common::TOKEN_DECIMALS as u32)
+}
+
+fn raw(value: &Value) -> u128 {
+    value
+        .as_str()
+        .expect("amounts are strings")
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn manages_accounts_and_labels() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    assert_eq!(bob, address("//Bob"));
+    let charlie = wallet.ok(&["add-account", "//Charlie", "--scheme", "ed25519"]);
+    assert_eq!(charlie["scheme"], "ed25519");
+    let dave = address("//Dave");
+    wallet.ok(&["watch-account", &dave]);
+    // already in the wallet, as a watched or as a full account
+    wallet.fails(&["watch-account", &dave], DATABASE_ERROR);
+    wallet.fails(&["watch-account", &bob], DATABASE_ERROR);
+    wallet.fails(&["watch-account", "not-an-address"], ADDRESS_ERROR);
+
+    let accounts = wallet.ok(&["get-accounts"])["accounts"].clone();
+    assert_eq!(accounts.as_array().unwrap().len(), 3);
+    assert!(accounts
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|account| account["address"] == dave.as_str() && account["watch_only"] == true));
+
+    // the last account added becomes the default one
+    assert_eq!(
+        wallet.ok(&["get-default-account"])["default_account"],
+        charlie["account"]
+    );
+    wallet.ok(&["set-default-account", &bob]);
+    assert_eq!(
+        wallet.ok(&["get-default-account"])["default_account"],
+        bob.as_str()
+    );
+
+    wallet.ok(&["set-label", &bob, "savings"]);
+    assert_eq!(
+        wallet.ok(&["get-balance", "savings"])["account"],
+        bob.as_str()
+    );
+    wallet.fails(&["set-label", &bob, &dave], CONFIG_ERROR);
+    wallet.ok(&["clear-label", "savings"]);
+    let error = wallet.fails(&["get-balance", "savings"], ADDRESS_ERROR);
+    assert_eq!(error["error"]["category"], "address");
+
+    assert_eq!(wallet.ok(&["encrypt-wallet"])["encrypted"], true);
+    assert_eq!(
+        wallet.ok(&["get-admin-account"])["admin_account"],
+        address(ADMIN).as_str()
+    );
+}
+
+#[test]
+fn mints_and_reads_values() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    assert_eq!(wallet.ok(&["get-min-fee"])["min_fee"], GENESIS_MIN_FEE);
+    let genesis = node.best_number().to_string();
+
+    let result = wallet.ok(&["mint", &bob, "12.5"]);
+    assert_eq!(result["status"], "in-block");
+    // the node reads the balance from its own storage layout
+    assert_eq!(node.balance(&common::account("//Bob")), 12_500_000);
+    wallet.ok(&["--raw", "mint", &bob, "500000"]);
+
+    let balance = wallet.ok(&["get-balance", &bob]);
+    assert_eq!(raw(&balance["balance"]), tokens(13));
+    assert_eq!(
+        raw(&wallet.ok(&["get-total-supply"])["total_supply"]),
+        node.supply()
+    );
+    let before = wallet.ok(&["--at", &genesis, "get-balance", &bob]);
+    assert_eq!(raw(&before["balance"]), 0);
+
+    let portfolio = wallet.ok(&["portfolio"]);
+    assert_eq!(raw(&portfolio["total"]), tokens(13));
+    assert_eq!(portfolio["accounts"][0]["supply_share"], "100.00%");
+
+    let error = wallet.fails(&["get-balance", "not-an-address"], ADDRESS_ERROR);
+    assert_eq!(error["error"]["code"], ADDRESS_ERROR);
+}
+
+#[test]
+fn transfers_amount_and_fee() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    wallet.ok(&["mint", &bob, "10"]);
+    let charlie = address("//Charlie");
+
+    let best = node.best_number();
+    let dry_run = wallet.ok(&["transfer", &charlie, "1", "--dry-run"]);
+    assert_eq!(dry_run["error"], Value::Null);
+    assert_eq!(node.best_number(), best, "dry run submitted");
+
+    let result = wallet.ok(&["transfer", &charlie, "4", "--wait-for", "finalized"]);
+    assert_eq!(result["status"], "finalized");
+    assert_eq!(
+        node.balance(&common::account("//Bob")),
+        tokens(6) - GENESIS_MIN_FEE as u128
+    );
+    assert_eq!(node.balance(&common::account("//Charlie")), tokens(4));
+
+    wallet.ok(&["transfer", &charlie, "1", "--txfee", "25"]);
+    assert_eq!(
+        node.balance(&common::account("//Bob")),
+        tokens(5) - GENESIS_MIN_FEE as u128 - 25
+    );
+}
+
+#[test]
+fn rejects_transfers_the_runtime_would_reject() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    wallet.ok(&["mint", &bob, "1"]);
+    let charlie = address("//Charlie");
+    let best = node.best_number();
+
+    let error = wallet.fails(&["transfer", &charlie, "1"], BALANCE_TOO_LOW);
+    assert_eq!(error["error"]["category"], "runtime");
+    wallet.fails(&["transfer", &charlie, "0.5", "--txfee", "1"], FEE_TOO_LOW);
+    let dry_run = wallet.fails(&["transfer", &charlie, "2", "--dry-run"], BALANCE_TOO_LOW);
+    assert_eq!(dry_run["error"]["code"], BALANCE_TOO_LOW);
+    // the report holds the error, no second error object follows it
+    assert!(dry_run["call"].is_string());
+    assert_eq!(node.best_number(), best, "rejected transfer submitted");
+
+    // watch-only accounts can't sign
+    wallet.ok(&["watch-account", &charlie]);
+    wallet.ok(&["set-default-account", &charlie]);
+    wallet.fails(&["transfer", &bob, "0.1"], KEYSTORE_ERROR);
+}
+
+#[test]
+fn runs_admin_calls() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let admin = address(ADMIN);
+    // the admin pays the fee of admin calls
+    wallet.fails(&["set-min-fee", "25"], BALANCE_TOO_LOW);
+    wallet.ok(&["mint", &admin, "1"]);
+
+    wallet.ok(&["set-min-fee", "25"]);
+    assert_eq!(wallet.ok(&["get-min-fee"])["min_fee"], 25);
+    assert_eq!(node.min_fee(), 25);
+    wallet.fails(&["set-min-fee", "30", "--txfee", "20"], FEE_TOO_LOW);
+
+    std::fs::write(wallet.path("runtime.wasm"), b"\0asm\x01\0\0\0").unwrap();
+    let spec_version = node.spec_version();
+    let result = wallet.ok(&["upgrade-runtime", &wallet.path("runtime.wasm")]);
+    assert_eq!(result["spec_version_before"], spec_version);
+    assert_eq!(result["spec_version_after"], spec_version + 1);
+
+    std::fs::write(wallet.path("runtime.txt"), b"not wasm").unwrap();
+    wallet.fails(
+        &["upgrade-runtime", &wallet.path("runtime.txt")],
+        DECODE_ERROR,
+    );
+}
+
+#[test]
+fn signs_offline_and_broadcasts() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    wallet.ok(&["mint", &bob, "3"]);
+    let charlie = address("//Charlie");
+    let (payload, signed) = (wallet.path("payload.tx"), wallet.path("signed.tx"));
+
+    let build = wallet.ok(&["tx", "build", "transfer", &charlie, "2", "--out", &payload]);
+    assert!(build["payload"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("transfer 2 {} from", common::TOKEN_SYMBOL)));
+    // signing needs no endpoint
+    let mut command = wallet.command(&["tx", "sign", &payload, &signed]);
+    command.env_remove("ADDR");
+    let (code, sign) = wallet.exec(command);
+    assert_eq!(code, 0, "{}", sign);
+    assert_eq!(sign["signer"], bob.as_str());
+    let result = wallet.ok(&["tx", "broadcast", &signed]);
+    assert_eq!(result["hash"], sign["hash"]);
+    assert_eq!(node.balance(&common::account("//Charlie")), tokens(2));
+
+    // signed while the balance was enough, rejected by the node once it no longer is
+    wallet.ok(&[
+        "tx", "build", "transfer", &charlie, "0.9", "--out", &payload,
+    ]);
+    wallet.ok(&["tx", "sign", &payload, &signed]);
+    wallet.ok(&["transfer", &charlie, "0.5"]);
+    wallet.fails(&["tx", "broadcast", &signed], BALANCE_TOO_LOW);
+
+    // an admin call signed by another account
+    wallet.ok(&["tx", "build", "mint", &bob, "5", "--out", &payload]);
+    wallet.ok(&["tx", "sign", &payload, &signed, "--account", &bob]);
+    wallet.fails(&["tx", "broadcast", &signed], NO_ADMIN_KEY);
+
+    // a payload changed after signing
+    wallet.ok(&["tx", "sign", &payload, &signed]);
+    let mut encoded = std::fs::read_to_string(&signed).unwrap().trim().to_owned();
+    let tampered = if &encoded[20..21] == "0" { "1" } else { "0" };
+    encoded.replace_range(20..21, tampered);
+    std::fs::write(&signed, encoded).unwrap();
+    let best = node.best_number();
+    wallet.fails(&["tx", "broadcast", &signed], KEYSTORE_ERROR);
+    assert_eq!(node.best_number(), best);
+}
+
+#[test]
+fn resumes_transfer_batches() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    wallet.ok(&["mint", &bob, "10"]);
+    let (charlie, dave) = (address("//Charlie"), address("//Dave"));
+    let batch = wallet.path("batch.csv");
+    std::fs::write(
+        &batch,
+        format!("recipient,amount,fee\n{},1.5,\n{},2,12\n", charlie, dave),
+    )
+    .unwrap();
+
+    assert_eq!(wallet.ok(&["transfer-batch", &batch])["transfers"], 2);
+    assert_eq!(node.balance(&common::account("//Charlie")), 1_500_000);
+    assert_eq!(node.balance(&common::account("//Dave")), tokens(2));
+    let results = std::fs::read_to_string(wallet.path("batch.results.csv")).unwrap();
+    assert_eq!(results.matches("success").count(), 2);
+
+    // every row already succeeded
+    assert_eq!(wallet.ok(&["transfer-batch", &batch])["transfers"], 0);
+    assert_eq!(node.balance(&common::account("//Dave")), tokens(2));
+
+    // a run that stopped before recording the outcome is settled from the ledger, not resubmitted
+    let interrupted: Vec<&str> = results
+        .lines()
+        .filter(|line| line.starts_with("row,") || line.contains(",pending,"))
+        .collect();
+    let resumed = wallet.path("interrupted.results.csv");
+    std::fs::write(&resumed, interrupted.join("\n") + "\n").unwrap();
+    let best = node.best_number();
+    let summary = wallet.ok(&["transfer-batch", &batch, "--results", &resumed]);
+    assert_eq!(summary["transfers"], 0);
+    assert_eq!(node.best_number(), best);
+    let resumed = std::fs::read_to_string(&resumed).unwrap();
+    assert_eq!(resumed.matches("success").count(), 2);
+
+    // nor is a row recorded as failed whose transfer made it into a block anyway
+    let failed = wallet.path("failed.results.csv");
+    std::fs::write(
+        &failed,
+        interrupted.join("\n").replace(",pending,", ",failed,") + "\n",
+    )
+    .unwrap();
+    let summary = wallet.ok(&["transfer-batch", &batch, "--results", &failed]);
+    assert_eq!(summary["transfers"], 0);
+    assert_eq!(node.best_number(), best);
+    assert_eq!(node.balance(&common::account("//Dave")), tokens(2));
+
+    // a transfer still in the pool is left out until it is settled
+    node.hold_extrinsics();
+    let eve = address("//Eve");
+    let held_batch = wallet.path("held.csv");
+    std::fs::write(&held_batch, format!("recipient,amount\n{},1\n", eve)).unwrap();
+    let held = wallet.path("held.results.csv");
+    wallet.ok(&[
+        "transfer-batch",
+        &held_batch,
+        "--results",
+        &held,
+        "--wait-for",
+        "submitted",
+    ]);
+    let results = std::fs::read_to_string(&held).unwrap();
+    let interrupted: Vec<&str> = results
+        .lines()
+        .filter(|line| line.starts_with("row,") || line.contains(",pending,"))
+        .collect();
+    std::fs::write(&held, interrupted.join("\n") + "\n").unwrap();
+    wallet.fails(
+        &["transfer-batch", &held_batch, "--results", &held],
+        TRANSACTION_ERROR,
+    );
+    assert_eq!(node.pending(), 1);
+    node.seal_pending();
+    let summary = wallet.ok(&["transfer-batch", &held_batch, "--results", &held]);
+    assert_eq!(summary["transfers"], 0);
+    assert_eq!(node.balance(&common::account("//Eve")), tokens(1));
+
+    std::fs::write(&batch, "recipient,amount\nnot-an-address,1\n").unwrap();
+    let results = wallet.path("invalid.results.csv");
+    let error = wallet.fails(
+        &["transfer-batch", &batch, "--results", &results],
+        ADDRESS_ERROR,
+    );
+    assert!(error["error"]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Row: 1,"));
+
+    std::fs::write(&batch, format!("recipient,amount\n{},100\n", charlie)).unwrap();
+    let results = wallet.path("large.results.csv");
+    wallet.fails(
+        &["transfer-batch", &batch, "--results", &results],
+        TRANSACTION_ERROR,
+    );
+}
+
+#[test]
+fn indexes_history() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    wallet.ok(&["mint", &bob, "5"]);
+    wallet.ok(&["transfer", &address("//Charlie"), "1"]);
+    let dave = address("//Dave");
+    wallet.ok(&["mint", &dave, "2"]);
+
+    let history = wallet.ok(&["history"])["transactions"].clone();
+    let calls: Vec<&str> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| record["call"].as_str().unwrap())
+        .collect();
+    assert_eq!(calls, ["mint", "transfer", "mint"]);
+    assert_eq!(history[1]["status"], "in-block");
+    let transfers = wallet.ok(&["history", "--call", "transfer"])["transactions"].clone();
+    assert_eq!(transfers.as_array().unwrap().len(), 1);
+
+    // a wallet that only watches the account finds its transactions in the blocks
+    let watcher = Wallet::new(&node);
+    watcher.ok(&["watch-account", &bob]);
+    let summary = watcher.ok(&["index"]);
+    assert_eq!(summary["found"], 2);
+    assert_eq!(summary["to"], node.best_number());
+    assert_eq!(watcher.ok(&["index"])["found"], 0);
+    let history = watcher.ok(&["history", &bob])["transactions"].clone();
+    assert_eq!(history.as_array().unwrap().len(), 2);
+    assert_eq!(history[0]["status"], "finalized");
+
+    // an account added later is scanned from genesis, the others are not scanned again
+    watcher.ok(&["watch-account", &dave]);
+    let summary = watcher.ok(&["index"]);
+    assert_eq!(summary["from"], 0);
+    assert_eq!(summary["found"], 1);
+    let history = watcher.ok(&["history", &dave])["transactions"].clone();
+    assert_eq!(history[0]["call"], "mint");
+    assert_eq!(
+        watcher.ok(&["history", &bob])["transactions"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn watches_balances() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    let mut child = wallet
+        .command(&["watch-balance", &bob, "--supply"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let (lines, received) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if lines.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let next = || -> Value {
+        let line = received
+            .recv_timeout(Duration::from_secs(30))
+            .expect("watch-balance prints a line");
+        serde_json::from_str(&line).unwrap()
+    };
+
+    // the current values come first
+    let (first, second) = (next(), next());
+    let mut items = [first["item"].clone(), second["item"].clone()];
+    items.sort_by_key(|item| item.to_string());
+    assert_eq!(items, ["balance", "total_supply"]);
+
+    wallet.ok(&["mint", &bob, "2"]);
+    let (first, second) = (next(), next());
+    let balance = if first["item"] == "balance" {
+        first
+    } else {
+        second
+    };
+    assert_eq!(balance["account"], bob.as_str());
+    assert_eq!(raw(&balance["old"]), 0);
+    assert_eq!(raw(&balance["new"]), tokens(2));
+    assert_eq!(balance["delta"], format!("+{}", tokens(2)));
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn verifies_values_against_the_state_root() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    wallet.ok(&["mint", &bob, "3"]);
+    let best = node.best_number().to_string();
+
+    let balance = wallet.ok(&["--verify", "--at", &best, "get-balance", &bob]);
+    assert_eq!(raw(&balance["balance"]), tokens(3));
+    // absent values are proven absent
+    let charlie = address("//Charlie");
+    let balance = wallet.ok(&["--verify", "get-balance", &charlie]);
+    assert_eq!(raw(&balance["balance"]), 0);
+    let fee = wallet.ok(&["--verify", "get-min-fee"]);
+    assert_eq!(fee["min_fee"], GENESIS_MIN_FEE);
+
+    node.forge_proofs();
+    let error = wallet.fails(&["--verify", "get-balance", &bob], RPC_ERROR);
+    assert_eq!(error["error"]["category"], "rpc");
+    // the same read is served without checking
+    let balance = wallet.ok(&["get-balance", &bob]);
+    assert_eq!(raw(&balance["balance"]), tokens(3));
+}
+
+#[test]
+fn follows_a_transaction_on_the_next_endpoint_when_the_first_goes_down() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    let (first, second) = (node.proxy(), node.proxy());
+    let endpoints = format!("{},{}", first.ws_url(), second.ws_url());
+    node.hold_extrinsics();
+
+    let child = wallet
+        .command(&["--endpoint", &endpoints, "mint", &bob, "1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    node.wait_until("the mint to reach the pool", |node| node.pending() == 1);
+    first.close();
+    // the wallet finds the transaction in the pool through the second endpoint, then in its block
+    node.wait_until("the wallet to read the pool", |node| {
+        node.pool_queries() > 0
+    });
+    node.seal_pending();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "stdout: {}\nstderr: {}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(result["status"], "in-block");
+    assert_eq!(node.balance(&common::account("//Bob")), tokens(1));
+    assert_eq!(node.pending(), 0);
+}
+
+#[test]
+fn returns_once_submitted_when_the_connection_drops_before_the_answer() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    let (first, second) = (node.proxy(), node.proxy());
+    let endpoints = format!("{},{}", first.ws_url(), second.ws_url());
+    node.hold_extrinsics();
+    node.delay_submission_answers(Duration::from_secs(30));
+
+    let child = wallet
+        .command(&[
+            "--endpoint",
+            &endpoints,
+            "mint",
+            &bob,
+            "1",
+            "--wait-for",
+            "submitted",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    node.wait_until("the mint to reach the pool", |node| node.pending() == 1);
+    first.close();
+
+    // found in the pool through the second endpoint, which is all submitted waits for
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "stdout: {}\nstderr: {}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result: Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(result["status"], "ready");
+    assert_eq!(node.pending(), 1);
+}
+
+#[test]
+fn uses_network_profiles() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let database = wallet.path("local.sql");
+    wallet.ok(&[
+        "network",
+        "add",
+        "local",
+        "--endpoint",
+        &node.ws_url(),
+        "--database",
+        &database,
+    ]);
+    wallet.ok(&["network", "use", "local"]);
+    let networks = wallet.ok(&["network", "list"])["networks"].clone();
+    assert_eq!(networks[0]["name"], "local");
+    assert_eq!(networks[0]["current"], true);
+
+    // the endpoint and the database come from the profile
+    let mut command = wallet.command(&["add-account", "//Bob"]);
+    command.env_remove("ADDR").env_remove("DATABASE_FILE");
+    let (code, json) = wallet.exec(command);
+    assert_eq!(code, 0, "{}", json);
+    assert!(std::path::Path::new(&database).exists());
+
+    // the admin of the profile isn't overridden by a seed from .env
+    let bob = address("//Bob");
+    wallet.ok(&[
+        "network",
+        "add",
+        "bob-admin",
+        "--endpoint",
+        &node.ws_url(),
+        "--database",
+        &database,
+        "--admin",
+        &bob,
+    ]);
+    std::fs::write(wallet.path(".env"), format!("ADMIN_SEED={}\n", ADMIN)).unwrap();
+    let (payload, signed) = (wallet.path("mint.payload"), wallet.path("mint.signed"));
+    wallet.ok(&["tx", "build", "mint", &bob, "1", "--out", &payload]);
+    let mut command = wallet.command(&[
+        "--network",
+        "bob-admin",
+        "--database",
+        &database,
+        "tx",
+        "sign",
+        &payload,
+        &signed,
+    ]);
+    command.env_remove("ADMIN_SEED");
+    let (code, json) = wallet.exec(command);
+    assert_eq!(code, 0, "{}", json);
+    assert_eq!(json["signer"], bob.as_str());
+
+    wallet.ok(&["network", "remove", "bob-admin"]);
+    wallet.ok(&["network", "remove", "local"]);
+    std::fs::remove_file(wallet.path(".env")).unwrap();
+    let mut command = wallet.command(&["get-min-fee"]);
+    command.env_remove("ADDR");
+    let (code, json) = wallet.exec(command);
+    assert_eq!(code, CONFIG_ERROR, "{}", json);
+}
+
+#[test]
+fn submits_over_http() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    let http = node.http_url();
+
+    let fee = wallet.ok(&["--endpoint", &http, "get-min-fee"]);
+    assert_eq!(fee["min_fee"], GENESIS_MIN_FEE);
+    // following the transaction needs a subscription
+    let error = wallet.fails(&["--endpoint", &http, "mint", &bob, "1"], RPC_ERROR);
+    assert_eq!(error["error"]["category"], "rpc");
+    let result = wallet.ok(&[
+        "--endpoint",
+        &http,
+        "mint",
+        &bob,
+        "1",
+        "--wait-for",
+        "submitted",
+    ]);
+    assert_eq!(result["status"], "ready");
+    assert_eq!(node.balance(&common::account("//Bob")), tokens(1));
+}
+
+#[test]
+fn does_not_resubmit_when_the_answer_is_lost() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let bob = wallet.add_account("//Bob");
+    let http = node.http_url();
+    node.delay_submission_answers(Duration::from_secs(3));
+    let best = node.best_number();
+
+    // the request times out after the node accepted the mint, which is then found in its block
+    let result = wallet.ok(&[
+        "--endpoint",
+        &http,
+        "--request-timeout",
+        "1",
+        "mint",
+        &bob,
+        "1",
+        "--wait-for",
+        "submitted",
+    ]);
+    assert_eq!(result["status"], "in-block");
+    assert_eq!(node.best_number(), best + 1);
+    assert_eq!(node.balance(&common::account("//Bob")), tokens(1));
+}
+
+#[test]
+fn fails_when_the_node_is_unreachable() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let error = wallet.fails(
+        &[
+            "--endpoint",
+            "ws://127.0.0.1:1",
+            "--connect-timeout",
+            "1",
+            "get-min-fee",
+        ],
+        RPC_ERROR,
+    );
+    assert_eq!(error["error"]["category"], "rpc");
+}
+
+#[test]
+fn rejects_unknown_subcommands() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    // clap reports usage errors on stderr and exits with 2, without panicking
+    let output = wallet.command(&["foo"]).output().expect("wallet runs");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("foo"));
+}
+
+#[test]
+fn trusts_the_ca_bundle() {
+    let node = start_node();
+    let wallet = Wallet::new(&node);
+    let proxy = node.tls_proxy();
+    let ca = wallet.path("ca.pem");
+    std::fs::write(&ca, proxy.ca_pem()).unwrap();
+
+    // the certificate of the endpoint is signed by a CA only the bundle holds
+    for url in [proxy.wss_url(), proxy.https_url()] {
+        let fee = wallet.ok(&["--endpoint", &url, "--ca-bundle", &ca, "get-min-fee"]);
+        assert_eq!(fee["min_fee"], GENESIS_MIN_FEE, "{}", url);
+    }
+    let error = wallet.fails(&["--endpoint", &proxy.wss_url(), "get-min-fee"], RPC_ERROR);
+    assert_eq!(error["error"]["category"], "rpc");
+    wallet.fails(
+        &[
+            "--endpoint",
+            &proxy.wss_url(),
+            "--ca-bundle",
+            &wallet.path("missing.pem"),
+            "get-min-fee",
+        ],
+        CONFIG_ERROR,
+    );
+}
@@ -0,0 +1,853 @@
+//! In-process stand-in for the node. It serves the rpc methods the wallet uses, over both ws
+//! and http, from an in-memory model of the runtime. Every accepted extrinsic is sealed in a
+//! block of its own, which is final straight away, unless the node is told to hold them in its
+//! pool. Proxies in front of the node stand for endpoints that can be taken down, or that are
+//! served over TLS with a certificate of a private CA
+
+use jsonrpsee::core::Error as RpcError;
+use jsonrpsee::server::{ServerBuilder, ServerHandle};
+use jsonrpsee::types::error::{CallError, ErrorObject, ErrorObjectOwned};
+use jsonrpsee::RpcModule;
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use serde_json::{json, Value};
+use sp_core::crypto::{AccountId32, Pair, Ss58Codec};
+use sp_core::hashing::blake2_256;
+use sp_core::sr25519;
+use sp_runtime::generic::{Digest, Header as GenericHeader};
+use sp_runtime::traits::{BlakeTwo256, Hash, Header as HeaderT, IdentifyAccount, Verify};
+use sp_runtime::{MultiSignature, MultiSigner};
+use sp_trie::{LayoutV1, MemoryDB, TrieDBMutBuilder, TrieMut};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+// storage layout of the runtime
+const SUPPLY_KEY: &[u8] = b"SUPPLY_KEY";
+const MIN_FEE_KEY: &[u8] = b"MIN_FEE_KEY";
+const BALANCES_PREFIX_KEY: &[u8] = b"BAL_";
+
+pub const TOKEN_DECIMALS: u8 = 6;
+pub const TOKEN_SYMBOL: &str = "UNIT";
+pub const GENESIS_MIN_FEE: u32 = 10;
+const SPEC_VERSION: u32 = 100;
+
+// error codes of the node for extrinsics that can't be decoded and for invalid ones
+const INVALID_FORMAT: i32 = 1001;
+const INVALID_TRANSACTION: i32 = 1010;
+const ALREADY_IMPORTED: i32 = 1013;
+
+type Header = GenericHeader<u64, BlakeTwo256>;
+
+// The runtime types are written out again rather than taken from the wallet, so that a change
+// of the encoding on the wallet side shows up as a rejected extrinsic
+#[derive(Encode, Decode, Debug, Clone)]
+enum Call {
+    Mint([u8; 32], u128),
+    Transfer([u8; 32], [u8; 32], u128),
+    SetMinFee(u32),
+    Upgrade(Vec<u8>),
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+struct ExtrinsicPayload {
+    call: Call,
+    fee: u32,
+}
+
+/// Raw sr25519 signature and public key, or the encoded MultiSignature and MultiSigner for the other schemes
+#[derive(Encode, Decode, Debug, Clone)]
+struct Signature {
+    signature: Vec<u8>,
+    origin: Vec<u8>,
+}
+
+impl Signature {
+    fn parts(&self) -> Option<(MultiSignature, MultiSigner)> {
+        if self.signature.len() == 64 && self.origin.len() == 32 {
+            let signature = sr25519::Signature::from_raw(self.signature[..].try_into().ok()?);
+            let origin = sr25519::Public::from_raw(self.origin[..].try_into().ok()?);
+            return Some((signature.into(), origin.into()));
+        }
+        Some((
+            MultiSignature::decode_all(&mut &self.signature[..]).ok()?,
+            MultiSigner::decode_all(&mut &self.origin[..]).ok()?,
+        ))
+    }
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+struct Extrinsic(ExtrinsicPayload, Option<Signature>);
+
+/// Errors of the runtime, reported as custom validity errors with the index of the variant as code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuntimeError {
+    NoSignature,
+    NoAdminKey,
+    InvalidSignature,
+    SupplyOverflow,
+    BalanceTooLow,
+    FeeTooLow,
+}
+
+struct Block {
+    header: Header,
+    extrinsics: Vec<Vec<u8>>,
+    /// State after the block, kept for every block so that values can be read at any of them
+    storage: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Block {
+    fn hash(&self) -> String {
+        hex_string(self.header.hash().as_ref())
+    }
+
+    fn header_json(&self) -> Value {
+        serde_json::to_value(&self.header).expect("headers serialize")
+    }
+}
+
+/// The chain as seen by the node, along with the rules of the runtime
+struct Chain {
+    admin: [u8; 32],
+    spec_version: u32,
+    blocks: Vec<Block>,
+    /// Number of every new block, for the storage and extrinsic subscriptions
+    new_blocks: broadcast::Sender<usize>,
+    /// Keep accepted extrinsics in the pool until they are sealed with seal_pending
+    hold: bool,
+    pool: Vec<Vec<u8>>,
+    pool_queries: usize,
+    /// Time taken to answer a submission, or to send the first status of a watched one, after the
+    /// extrinsic was accepted
+    submit_delay: Duration,
+    /// Serve read proofs of a storage other than the one of the block
+    forge_proofs: bool,
+}
+
+impl Chain {
+    fn new(admin: [u8; 32]) -> Chain {
+        let mut storage = BTreeMap::new();
+        storage.insert(MIN_FEE_KEY.to_vec(), GENESIS_MIN_FEE.encode());
+        storage.insert(SUPPLY_KEY.to_vec(), 0u128.encode());
+        let (new_blocks, _) = broadcast::channel(16);
+        let mut chain = Chain {
+            admin,
+            spec_version: SPEC_VERSION,
+            blocks: Vec::new(),
+            new_blocks,
+            hold: false,
+            pool: Vec::new(),
+            pool_queries: 0,
+            submit_delay: Duration::ZERO,
+            forge_proofs: false,
+        };
+        chain.seal(Vec::new(), storage);
+        chain
+    }
+
+    fn best(&self) -> &Block {
+        self.blocks.last().expect("genesis is sealed on creation")
+    }
+
+    fn find(&self, hash: &str) -> Option<&Block> {
+        self.blocks
+            .iter()
+            .find(|block| block.hash().eq_ignore_ascii_case(hash))
+    }
+
+    /// The block at the given hash, or the best block if none is given
+    fn block(&self, at: Option<&str>) -> Result<&Block, RpcError> {
+        match at {
+            None => Ok(self.best()),
+            Some(hash) => self
+                .find(hash)
+                .ok_or_else(|| call_error(-32602, &format!("Unknown block: {}", hash))),
+        }
+    }
+
+    fn seal(&mut self, extrinsics: Vec<Vec<u8>>, storage: BTreeMap<Vec<u8>, Vec<u8>>) {
+        let (number, parent_hash) = match self.blocks.last() {
+            Some(parent) => (parent.header.number + 1, parent.header.hash()),
+            None => (0, Default::default()),
+        };
+        let header = Header::new(
+            number,
+            BlakeTwo256::hash_of(&extrinsics),
+            storage_trie(&storage).0,
+            parent_hash,
+            Digest::default(),
+        );
+        self.blocks.push(Block {
+            header,
+            extrinsics,
+            storage,
+        });
+        // nobody may be watching, which is fine
+        let _ = self.new_blocks.send(number as usize);
+    }
+
+    /// Executes the extrinsic on top of the best block and seals it in a new block, returns the block
+    /// hash, or None when the extrinsic is held in the pool
+    fn submit(&mut self, encoded: &str) -> Result<Option<String>, ErrorObjectOwned> {
+        let bytes = hex::decode(encoded.trim_start_matches("0x"))
+            .ok()
+            .filter(|bytes| Extrinsic::decode_all(&mut &bytes[..]).is_ok())
+            .ok_or_else(|| {
+                ErrorObject::owned(
+                    INVALID_FORMAT,
+                    "Extrinsic has invalid format",
+                    Some("Could not decode the extrinsic"),
+                )
+            })?;
+        // the same extrinsic is only accepted once, the runtime has no nonce to tell copies apart
+        let known = self.pool.contains(&bytes)
+            || self
+                .blocks
+                .iter()
+                .any(|block| block.extrinsics.contains(&bytes));
+        if known {
+            return Err(ErrorObject::owned(
+                ALREADY_IMPORTED,
+                "Transaction Already Imported",
+                Some("The transaction is already in the pool or in a block"),
+            ));
+        }
+        let ext = Extrinsic::decode_all(&mut &bytes[..]).expect("checked above");
+        let storage = self.execute(&ext).map_err(|error| {
+            ErrorObject::owned(
+                INVALID_TRANSACTION,
+                "Invalid Transaction",
+                Some(format!("Custom error: {}", error as u8)),
+            )
+        })?;
+        if self.hold {
+            self.pool.push(bytes);
+            return Ok(None);
+        }
+        self.include(ext, bytes, storage);
+        Ok(Some(self.best().hash()))
+    }
+
+    fn include(&mut self, ext: Extrinsic, bytes: Vec<u8>, storage: BTreeMap<Vec<u8>, Vec<u8>>) {
+        if let Call::Upgrade(_) = ext.0.call {
+            self.spec_version += 1;
+        }
+        self.seal(vec![bytes], storage);
+    }
+
+    /// Seals every extrinsic of the pool in a block of its own, dropping the ones that became invalid
+    fn seal_pending(&mut self) {
+        for bytes in std::mem::take(&mut self.pool) {
+            let ext = Extrinsic::decode_all(&mut &bytes[..]).expect("checked on submission");
+            if let Ok(storage) = self.execute(&ext) {
+                self.include(ext, bytes, storage);
+            }
+        }
+    }
+
+    /// Number of the block holding the extrinsic, searched from the given block on
+    fn find_extrinsic(&self, bytes: &[u8], from: usize) -> Option<usize> {
+        (from..self.blocks.len()).find(|number| {
+            self.blocks[*number]
+                .extrinsics
+                .iter()
+                .any(|ext| ext == bytes)
+        })
+    }
+
+    /// The rules of the runtime: admin calls are signed by the admin, fees are at least the min fee and
+    /// come out of the signer balance along with the amount of transfers
+    fn execute(&self, ext: &Extrinsic) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, RuntimeError> {
+        let signature = ext.1.as_ref().ok_or(RuntimeError::NoSignature)?;
+        let (signature, origin) = signature.parts().ok_or(RuntimeError::InvalidSignature)?;
+        let signer_id = origin.into_account();
+        if !signature.verify(&ext.0.encode()[..], &signer_id) {
+            return Err(RuntimeError::InvalidSignature);
+        }
+        let signer: [u8; 32] = signer_id.into();
+        let mut storage = self.best().storage.clone();
+        let min_fee: u32 = read(&storage, MIN_FEE_KEY);
+        let fee = ext.0.fee as u128;
+        let is_admin_call = !matches!(ext.0.call, Call::Transfer(..));
+        if is_admin_call && signer != self.admin {
+            return Err(RuntimeError::NoAdminKey);
+        }
+        match &ext.0.call {
+            Call::Mint(account, amount) => {
+                let supply: u128 = read(&storage, SUPPLY_KEY);
+                let supply = supply
+                    .checked_add(*amount)
+                    .ok_or(RuntimeError::SupplyOverflow)?;
+                let balance: u128 = read(&storage, &balance_key(account));
+                storage.insert(SUPPLY_KEY.to_vec(), supply.encode());
+                storage.insert(balance_key(account), (balance + amount).encode());
+            }
+            Call::Transfer(from, to, amount) => {
+                // the signature has to be the one of the sender
+                if *from != signer {
+                    return Err(RuntimeError::InvalidSignature);
+                }
+                if ext.0.fee < min_fee {
+                    return Err(RuntimeError::FeeTooLow);
+                }
+                let from_balance: u128 = read(&storage, &balance_key(from));
+                let from_balance = from_balance
+                    .checked_sub(amount.saturating_add(fee))
+                    .ok_or(RuntimeError::BalanceTooLow)?;
+                storage.insert(balance_key(from), from_balance.encode());
+                let to_balance: u128 = read(&storage, &balance_key(to));
+                storage.insert(balance_key(to), (to_balance + amount).encode());
+            }
+            Call::SetMinFee(_) | Call::Upgrade(_) => {
+                if ext.0.fee < min_fee {
+                    return Err(RuntimeError::FeeTooLow);
+                }
+                let balance: u128 = read(&storage, &balance_key(&signer));
+                let balance = balance
+                    .checked_sub(fee)
+                    .ok_or(RuntimeError::BalanceTooLow)?;
+                storage.insert(balance_key(&signer), balance.encode());
+                if let Call::SetMinFee(new_min_fee) = ext.0.call {
+                    storage.insert(MIN_FEE_KEY.to_vec(), new_min_fee.encode());
+                }
+            }
+        }
+        Ok(storage)
+    }
+
+    /// Change set of the keys at the block, only with the values that differ from the parent block
+    /// unless all of them are asked for
+    fn changes(&self, keys: &[Vec<u8>], number: usize, all: bool) -> Option<Value> {
+        let block = &self.blocks[number];
+        let changes: Vec<Value> = keys
+            .iter()
+            .filter(|key| {
+                all || number == 0
+                    || self.blocks[number - 1].storage.get(*key) != block.storage.get(*key)
+            })
+            .map(|key| {
+                json!([
+                    hex_string(key),
+                    block.storage.get(key).map(|value| hex_string(value))
+                ])
+            })
+            .collect();
+        if changes.is_empty() && !all {
+            return None;
+        }
+        Some(json!({ "block": block.hash(), "changes": changes }))
+    }
+}
+
+/// A running mock node, stopped when dropped
+pub struct MockNode {
+    chain: Arc<Mutex<Chain>>,
+    addr: SocketAddr,
+    _handle: ServerHandle,
+    runtime: Runtime,
+}
+
+/// Endpoint forwarding connections to the node, until it is closed or dropped
+pub struct Proxy {
+    addr: SocketAddr,
+    /// PEM certificate of the CA that signed the one of a proxy over TLS
+    ca: Option<String>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Proxy {
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Url of a proxy over TLS, whose certificate is for localhost
+    pub fn wss_url(&self) -> String {
+        format!("wss://localhost:{}", self.addr.port())
+    }
+
+    pub fn https_url(&self) -> String {
+        format!("https://localhost:{}", self.addr.port())
+    }
+
+    pub fn ca_pem(&self) -> &str {
+        self.ca.as_deref().expect("proxy over TLS")
+    }
+
+    /// Stops accepting connections and drops the open ones, the way a node that goes down does
+    pub fn close(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl MockNode {
+    /// Starts a node whose runtime accepts admin calls from the given account
+    pub fn start(admin: &AccountId32) -> MockNode {
+        let chain = Arc::new(Mutex::new(Chain::new(admin.clone().into())));
+        let runtime = Runtime::new().expect("tokio runtime");
+        let module = rpc_module(chain.clone());
+        let (addr, handle) = runtime.block_on(async {
+            let server = ServerBuilder::default()
+                .build("127.0.0.1:0")
+                .await
+                .expect("mock node binds to a local port");
+            let addr = server.local_addr().expect("bound address");
+            (addr, server.start(module).expect("mock node starts"))
+        });
+        MockNode {
+            chain,
+            addr,
+            _handle: handle,
+            runtime,
+        }
+    }
+
+    /// A new endpoint in front of the node
+    pub fn proxy(&self) -> Proxy {
+        self.forward(None)
+    }
+
+    /// A new endpoint over TLS in front of the node, its certificate is signed by a CA of its own
+    pub fn tls_proxy(&self) -> Proxy {
+        let mut ca = rcgen::CertificateParams::new(Vec::new());
+        ca.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca).expect("CA certificate");
+        let server = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+            "localhost".to_owned(),
+        ]))
+        .expect("server certificate");
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(
+                    server
+                        .serialize_der_with_signer(&ca)
+                        .expect("certificate signed by the CA"),
+                )],
+                rustls::PrivateKey(server.serialize_private_key_der()),
+            )
+            .expect("TLS config");
+        let mut proxy = self.forward(Some(TlsAcceptor::from(Arc::new(config))));
+        proxy.ca = Some(ca.serialize_pem().expect("CA certificate in PEM"));
+        proxy
+    }
+
+    fn forward(&self, acceptor: Option<TlsAcceptor>) -> Proxy {
+        let node = self.addr;
+        let tasks = Arc::new(Mutex::new(Vec::new()));
+        let listener = self
+            .runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("proxy binds to a local port");
+        let addr = listener.local_addr().expect("bound address");
+        let connections = tasks.clone();
+        let accept = self.runtime.spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let connection = tokio::spawn(async move {
+                    if let Ok(mut outbound) = TcpStream::connect(node).await {
+                        match acceptor {
+                            Some(acceptor) => {
+                                if let Ok(mut inbound) = acceptor.accept(inbound).await {
+                                    let _ =
+                                        tokio::io::copy_bidirectional(&mut inbound, &mut outbound)
+                                            .await;
+                                }
+                            }
+                            None => {
+                                let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound)
+                                    .await;
+                            }
+                        }
+                    }
+                });
+                connections.lock().unwrap().push(connection);
+            }
+        });
+        tasks.lock().unwrap().push(accept);
+        Proxy {
+            addr,
+            ca: None,
+            tasks,
+        }
+    }
+
+    /// Keeps the extrinsics submitted from now on in the pool, until seal_pending is called
+    pub fn hold_extrinsics(&self) {
+        self.chain.lock().unwrap().hold = true;
+    }
+
+    /// Makes read proofs up from a storage holding one more value than the block, so that they don't
+    /// match its state root
+    pub fn forge_proofs(&self) {
+        self.chain.lock().unwrap().forge_proofs = true;
+    }
+
+    /// Answers submissions only after the delay, once the extrinsic is accepted
+    pub fn delay_submission_answers(&self, delay: Duration) {
+        self.chain.lock().unwrap().submit_delay = delay;
+    }
+
+    pub fn seal_pending(&self) {
+        self.chain.lock().unwrap().seal_pending();
+    }
+
+    pub fn pending(&self) -> usize {
+        self.chain.lock().unwrap().pool.len()
+    }
+
+    /// Number of times the pool was read with author_pendingExtrinsics
+    pub fn pool_queries(&self) -> usize {
+        self.chain.lock().unwrap().pool_queries
+    }
+
+    /// Waits for the condition to hold, panics if it doesn't within the timeout
+    pub fn wait_until(&self, what: &str, condition: impl Fn(&MockNode) -> bool) {
+        let start = Instant::now();
+        while !condition(self) {
+            assert!(
+                start.elapsed() < Duration::from_secs(20),
+                "timed out waiting for {}",
+                what
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    pub fn http_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn balance(&self, account: &AccountId32) -> u128 {
+        let chain = self.chain.lock().unwrap();
+        read(&chain.best().storage, &balance_key(&account.clone().into()))
+    }
+
+    pub fn supply(&self) -> u128 {
+        read(&self.chain.lock().unwrap().best().storage, SUPPLY_KEY)
+    }
+
+    pub fn min_fee(&self) -> u32 {
+        read(&self.chain.lock().unwrap().best().storage, MIN_FEE_KEY)
+    }
+
+    pub fn best_number(&self) -> u64 {
+        self.chain.lock().unwrap().best().header.number
+    }
+
+    pub fn spec_version(&self) -> u32 {
+        self.chain.lock().unwrap().spec_version
+    }
+}
+
+fn rpc_module(chain: Arc<Mutex<Chain>>) -> RpcModule<Arc<Mutex<Chain>>> {
+    let mut module = RpcModule::new(chain);
+    module
+        .register_method("state_getStorage", |params, chain| {
+            let mut params = params.sequence();
+            let key: String = params.next()?;
+            let at: Option<String> = params.optional_next()?;
+            let chain = chain.lock().unwrap();
+            let block = chain.block(at.as_deref())?;
+            Ok(block
+                .storage
+                .get(&decode_hex(&key)?)
+                .map(|value| hex_string(value)))
+        })
+        .unwrap();
+    module
+        .register_method("state_queryStorageAt", |params, chain| {
+            let mut params = params.sequence();
+            let keys: Vec<String> = params.next()?;
+            let at: Option<String> = params.optional_next()?;
+            let chain = chain.lock().unwrap();
+            let block = chain.block(at.as_deref())?;
+            let mut changes = Vec::new();
+            for key in keys {
+                let value = block
+                    .storage
+                    .get(&decode_hex(&key)?)
+                    .map(|value| hex_string(value));
+                changes.push(json!([key, value]));
+            }
+            Ok(vec![json!({ "block": block.hash(), "changes": changes })])
+        })
+        .unwrap();
+    module
+        .register_method("state_getReadProof", |params, chain| {
+            let mut params = params.sequence();
+            let keys: Vec<String> = params.next()?;
+            let at: Option<String> = params.optional_next()?;
+            let chain = chain.lock().unwrap();
+            let block = chain.block(at.as_deref())?;
+            for key in &keys {
+                decode_hex(key)?;
+            }
+            let mut storage = block.storage.clone();
+            if chain.forge_proofs {
+                storage.insert(b"FORGED".to_vec(), vec![1]);
+            }
+            // every node of the trie, more than needed for the keys but a valid proof of them all
+            let (_, mut db) = storage_trie(&storage);
+            let proof: Vec<String> = db
+                .drain()
+                .into_values()
+                .filter(|(_, references)| *references > 0)
+                .map(|(node, _)| hex_string(&node))
+                .collect();
+            Ok(json!({ "at": block.hash(), "proof": proof }))
+        })
+        .unwrap();
+    module
+        .register_method("chain_getBlockHash", |params, chain| {
+            let number: Option<usize> = params.sequence().optional_next()?;
+            let chain = chain.lock().unwrap();
+            Ok(match number {
+                Some(number) => chain.blocks.get(number).map(Block::hash),
+                None => Some(chain.best().hash()),
+            })
+        })
+        .unwrap();
+    module
+        .register_method("chain_getHeader", |params, chain| {
+            let hash: Option<String> = params.sequence().optional_next()?;
+            let chain = chain.lock().unwrap();
+            Ok(match hash {
+                Some(hash) => chain.find(&hash).map(Block::header_json),
+                None => Some(chain.best().header_json()),
+            })
+        })
+        .unwrap();
+    module
+        .register_method("chain_getFinalizedHead", |_, chain| {
+            Ok(chain.lock().unwrap().best().hash())
+        })
+        .unwrap();
+    module
+        .register_method("chain_getBlock", |params, chain| {
+            let hash: Option<String> = params.sequence().optional_next()?;
+            let chain = chain.lock().unwrap();
+            let block = match hash {
+                Some(hash) => chain.find(&hash),
+                None => Some(chain.best()),
+            };
+            Ok(block.map(|block| {
+                let extrinsics: Vec<String> =
+                    block.extrinsics.iter().map(|ext| hex_string(ext)).collect();
+                json!({
+                    "block": { "header": block.header_json(), "extrinsics": extrinsics },
+                    "justifications": null,
+                })
+            }))
+        })
+        .unwrap();
+    module
+        .register_method("state_getRuntimeVersion", |_, chain| {
+            Ok(json!({
+                "specName": "mock",
+                "implName": "mock-node",
+                "authoringVersion": 1,
+                "specVersion": chain.lock().unwrap().spec_version,
+                "implVersion": 1,
+                "apis": [],
+                "transactionVersion": 1,
+                "stateVersion": 1,
+            }))
+        })
+        .unwrap();
+    module
+        .register_method("system_properties", |_, _| {
+            Ok(json!({ "tokenDecimals": TOKEN_DECIMALS, "tokenSymbol": TOKEN_SYMBOL }))
+        })
+        .unwrap();
+    module
+        .register_async_method("author_submitExtrinsic", |params, chain| async move {
+            let encoded: String = params.one()?;
+            let delay = {
+                let mut chain = chain.lock().unwrap();
+                chain
+                    .submit(&encoded)
+                    .map_err(|error| RpcError::Call(CallError::Custom(error)))?;
+                chain.submit_delay
+            };
+            // the extrinsic is in, only the answer is late
+            tokio::time::sleep(delay).await;
+            Ok(hex_string(&blake2_256(&decode_hex(&encoded)?)))
+        })
+        .unwrap();
+    module
+        .register_method("author_pendingExtrinsics", |_, chain| {
+            let mut chain = chain.lock().unwrap();
+            chain.pool_queries += 1;
+            Ok(chain
+                .pool
+                .iter()
+                .map(|ext| hex_string(ext))
+                .collect::<Vec<_>>())
+        })
+        .unwrap();
+    module
+        .register_subscription(
+            "author_submitAndWatchExtrinsic",
+            "author_extrinsicUpdate",
+            "author_unwatchExtrinsic",
+            |params, mut sink, chain| {
+                let encoded: String = params.one()?;
+                let bytes = hex::decode(encoded.trim_start_matches("0x"))
+                    .map_err(|_| ErrorObject::owned(-32602, "Invalid hex", None::<()>))?;
+                let chain = (*chain).clone();
+                let (submitted, mut new_blocks, from, delay) = {
+                    let mut chain = chain.lock().unwrap();
+                    let from = chain.blocks.len();
+                    let submitted = chain.submit(&encoded);
+                    (
+                        submitted,
+                        chain.new_blocks.subscribe(),
+                        from,
+                        chain.submit_delay,
+                    )
+                };
+                let block = match submitted {
+                    Ok(block) => block,
+                    Err(error) => return Ok(sink.reject(error)?),
+                };
+                tokio::spawn(async move {
+                    // the extrinsic is in, only the answer is late
+                    tokio::time::sleep(delay).await;
+                    if !sink.send(&json!("ready")).unwrap_or(false) {
+                        return;
+                    }
+                    let block = match block {
+                        Some(block) => block,
+                        // held in the pool, reported once a block includes it
+                        None => loop {
+                            if new_blocks.recv().await.is_err() {
+                                return;
+                            }
+                            let chain = chain.lock().unwrap();
+                            if let Some(number) = chain.find_extrinsic(&bytes, from) {
+                                break chain.blocks[number].hash();
+                            }
+                        },
+                    };
+                    for status in [json!({ "inBlock": block }), json!({ "finalized": block })] {
+                        if !sink.send(&status).unwrap_or(false) {
+                            break;
+                        }
+                    }
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    module
+        .register_subscription(
+            "state_subscribeStorage",
+            "state_storage",
+            "state_unsubscribeStorage",
+            |params, mut sink, chain| {
+                let keys: Vec<String> = params.one()?;
+                let keys = keys
+                    .iter()
+                    .map(|key| hex::decode(key.trim_start_matches("0x")))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| ErrorObject::owned(-32602, "Invalid storage key", None::<()>))?;
+                let chain = (*chain).clone();
+                let (mut new_blocks, current) = {
+                    let chain = chain.lock().unwrap();
+                    let current = chain.changes(&keys, chain.blocks.len() - 1, true);
+                    (chain.new_blocks.subscribe(), current)
+                };
+                // the first notification holds the current values
+                if !sink.send(&current).unwrap_or(false) {
+                    return Ok(());
+                }
+                tokio::spawn(async move {
+                    while let Ok(number) = new_blocks.recv().await {
+                        let changes = chain.lock().unwrap().changes(&keys, number, false);
+                        if let Some(changes) = changes {
+                            if !sink.send(&changes).unwrap_or(false) {
+                                break;
+                            }
+                        }
+                    }
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+    module
+}
+
+/// The account of a secret uri like //Alice, for the sr25519 scheme the wallet uses by default
+pub fn account(suri: &str) -> AccountId32 {
+    sr25519::Pair::from_string(suri, None)
+        .expect("valid secret uri")
+        .public()
+        .into()
+}
+
+pub fn address(suri: &str) -> String {
+    account(suri).to_ss58check()
+}
+
+/// Trie of the storage, whose root is the state root of the block header and whose nodes make up read proofs
+fn storage_trie(storage: &BTreeMap<Vec<u8>, Vec<u8>>) -> (sp_core::H256, MemoryDB<BlakeTwo256>) {
+    let mut db = MemoryDB::default();
+    let mut root = Default::default();
+    {
+        let mut trie = TrieDBMutBuilder::<LayoutV1<BlakeTwo256>>::new(&mut db, &mut root).build();
+        for (key, value) in storage {
+            trie.insert(key, value).expect("in-memory trie");
+        }
+    }
+    (root, db)
+}
+
+fn balance_key(account: &[u8; 32]) -> Vec<u8> {
+    [BALANCES_PREFIX_KEY, &account[..]].concat()
+}
+
+/// Reads a value of the storage, a missing value is the default one
+fn read<T: Decode + Default>(storage: &BTreeMap<Vec<u8>, Vec<u8>>, key: &[u8]) -> T {
+    storage
+        .get(key)
+        .map(|value| T::decode(&mut &value[..]).expect("values are encoded by the runtime"))
+        .unwrap_or_default()
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, RpcError> {
+    hex::decode(value.trim_start_matches("0x"))
+        .map_err(|_| call_error(-32602, &format!("Invalid hex: {}", value)))
+}
+
+fn call_error(code: i32, message: &str) -> RpcError {
+    RpcError::Call(CallError::Custom(ErrorObject::owned(
+        code,
+        message.to_owned(),
+        None::<()>,
+    )))
+}